@@ -0,0 +1,32 @@
+//! Step throughput of the interpreter.
+//!
+//! Run with `cargo bench`. Each benchmark steps a program which never ends
+//! and reports how many million steps a second it managed.
+
+extern crate rubefunge_93;
+
+use std::time::Instant;
+
+use rubefunge_93::befunge::{Interpreter, Program};
+
+/// Step `source` `steps` times and print the throughput.
+fn bench(name: &str, source: &str, steps: usize) {
+	let mut interpreter = Interpreter::from_program(Program::from_str(source));
+
+	let start = Instant::now();
+	for _ in 0..steps {
+		interpreter.step();
+	}
+	let elapsed = start.elapsed();
+
+	println!("{}: {} steps in {:?} ({:.1} M steps/s)",
+		name, steps, elapsed, steps as f64 / elapsed.as_secs_f64() / 1e6);
+}
+
+fn main() {
+	// Push ten digits then pop them again, forever.
+	bench("digits", "0123456789$$$$$$$$$$", 10_000_000);
+
+	// A small loop which only moves and adds.
+	bench("loop", ">1+v\n^  <", 20_000_000);
+}
@@ -8,23 +8,561 @@
 //! use rubefunge_93::befunge;
 //!
 //! // Create a random number generator
-//! let values = vec![0u32];
+//! let values = vec![0i64];
 //! let instructions = vec!["1248::+1> #+?\\# _.@".chars().collect()];
 //!
-//! let program = befunge::Program::new(values, instructions);
-//! let mut interpreter = befunge::Interpreter::from_program(program);
+//! let program = befunge::Program::new(instructions);
+//! let mut interpreter = befunge::Interpreter::with_values(program, values);
 //!
-//! interpreter.execute();
+//! interpreter.execute().unwrap();
 //! ```
 
-use rand::{Rng, thread_rng};
+use rand::{Rng, SeedableRng, StdRng, thread_rng};
+use std::cell::RefCell;
 use std::char;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// Every character with a meaning in Befunge-93.
+pub const INSTRUCTIONS: &str = "0123456789+-*/%!`><^v?_|\":\\$.,#pg&~@";
+
+/// The newline sequence used by the current platform.
+#[cfg(windows)]
+pub const PLATFORM_NEWLINE: &str = "\r\n";
+
+/// The newline sequence used by the current platform.
+#[cfg(not(windows))]
+pub const PLATFORM_NEWLINE: &str = "\n";
+
+/// Symbol shown in place of a tab cell when rendering the grid.
+pub const TAB_SYMBOL: char = '→';
+
+/// Width of the Befunge-93 playfield.
+pub const PLAYFIELD_WIDTH: usize = 80;
+
+/// Height of the Befunge-93 playfield.
+pub const PLAYFIELD_HEIGHT: usize = 25;
+
+/// Maximum number of instructions run by `fuzz_run`.
+const FUZZ_MAX_STEPS: usize = 10_000;
+
+/// Maximum stack size allowed by `fuzz_run`.
+const FUZZ_MAX_STACK: usize = 1_000;
+
+/// Instructions run between checks of the clock by `execute_with_timeout`.
+const TIMEOUT_CHECK_INTERVAL: usize = 1_000;
+
+/// Maximum instructions run by `run` and each run of `run_many`.
+const RUN_MAX_STEPS: usize = 1_000_000;
+
+/// Converts a stack value into a grid coordinate.
+///
+/// Returns `None` for negative values, which can never be coordinates, and
+/// for values too large for a `usize`. Only targets with a `usize` narrower
+/// than 64 bits have such values. Whether the coordinate lies within a
+/// program must be checked separately.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::to_coord;
+///
+/// assert_eq!(to_coord(-1), None);
+/// assert_eq!(to_coord(i64::MIN), None);
+/// assert_eq!(to_coord(0), Some(0));
+/// assert_eq!(to_coord(79), Some(79));
+///
+/// #[cfg(target_pointer_width = "64")]
+/// assert_eq!(to_coord(i64::MAX), Some(i64::MAX as usize));
+/// #[cfg(not(target_pointer_width = "64"))]
+/// assert_eq!(to_coord(i64::MAX), None);
+/// ```
+pub fn to_coord(value: i64) -> Option<usize> {
+	usize::try_from(value).ok()
+}
+
+/// Converts a stack value into a character, if it is a valid code point.
+fn to_char(value: i64) -> Option<char> {
+	if value < 0 || value > i64::from(u32::MAX) {
+		None
+	} else {
+		char::from_u32(value as u32)
+	}
+}
+
+/// Run arbitrary bytes as a Befunge program, for use as a fuzz target.
+///
+/// The bytes are loaded with `Program::from_str`, as a program file would
+/// be, after replacing invalid UTF-8 with U+FFFD. The program runs with no
+/// user values and its output is discarded. Execution stops after a small
+/// number of steps or if the stack grows too large. This never panics.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge;
+///
+/// befunge::fuzz_run(b"");
+/// befunge::fuzz_run(b"pppp\npppp");
+/// befunge::fuzz_run(b"????\n\n??");
+/// befunge::fuzz_run(&[0xff, b'0', b'/', b',', b'\r', b'\n', b'&', b'g']);
+/// ```
+pub fn fuzz_run(source: &[u8]) {
+	let program = Program::from_str(&String::from_utf8_lossy(source));
+
+	let mut interpreter = Interpreter::from_program(program);
+	interpreter.output = Box::new(io::sink());
+	interpreter.input = Box::new(io::empty());
+
+	for _ in 0..FUZZ_MAX_STEPS {
+		if interpreter.step() == StepOutcome::Ended || interpreter.stack.stack.len() > FUZZ_MAX_STACK {
+			break;
+		}
+	}
+}
+
+/// The kind of a cell, as found by `tokenize`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TokenKind {
+	/// A digit, `0` to `9`.
+	Digit,
+	/// An arithmetic, logical or stack instruction, such as `+`, `!` or `:`.
+	Operator,
+	/// An instruction which moves the pointer: `><^v?_|#`.
+	Movement,
+	/// An instruction which reads or writes: `.,&~` and the playfield access `g` and `p`.
+	Io,
+	/// The end instruction `@` or a `"` starting or ending a string.
+	Control,
+	/// A cell between a pair of `"` on the same line.
+	StringLiteral,
+	/// A space or any other character which is not an instruction.
+	Other,
+}
+
+/// A single cell of a program, as found by `tokenize`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Token {
+	/// The character in the cell.
+	pub c: char,
+	/// What the character means.
+	pub kind: TokenKind,
+	/// The `[row, col]` position of the cell.
+	pub pos: [usize; 2],
+}
+
+/// Split source text into lines of classified cells, for syntax highlighting.
+///
+/// This reads each line from left to right without running the program, so
+/// a string only covers the cells between two `"` on the same line. A `"`
+/// with no partner marks the rest of its line as a string.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{tokenize, TokenKind};
+///
+/// let tokens = tokenize("1\"a+\".@");
+/// let kinds: Vec<TokenKind> = tokens[0].iter().map(|token| token.kind).collect();
+///
+/// assert_eq!(kinds, vec![TokenKind::Digit, TokenKind::Control, TokenKind::StringLiteral,
+///                        TokenKind::StringLiteral, TokenKind::Control, TokenKind::Io,
+///                        TokenKind::Control]);
+/// assert_eq!(tokens[0][3].c, '+');
+/// assert_eq!(tokens[0][3].pos, [0, 3]);
+/// ```
+pub fn tokenize(source: &str) -> Vec<Vec<Token>> {
+	let program = Program::from_str(source);
+
+	program.instructions.iter()
+		.enumerate()
+		.map(|(row, line)| {
+			let mut in_string = false;
+			line.iter()
+				.enumerate()
+				.map(|(col, &c)| {
+					let kind = if c == '"' {
+						in_string = !in_string;
+						TokenKind::Control
+					} else if in_string {
+						TokenKind::StringLiteral
+					} else {
+						token_kind(c)
+					};
+					Token { c, kind, pos: [row, col] }
+				})
+				.collect()
+		})
+		.collect()
+}
+
+/// The kind of a character outside of a string.
+fn token_kind(c: char) -> TokenKind {
+	match c {
+		'0'..='9' => TokenKind::Digit,
+		'+' | '-' | '*' | '/' | '%' | '!' | '`' | ':' | '\\' | '$' => TokenKind::Operator,
+		'>' | '<' | '^' | 'v' | '?' | '_' | '|' | '#' => TokenKind::Movement,
+		'.' | ',' | '&' | '~' | 'g' | 'p' => TokenKind::Io,
+		'@' => TokenKind::Control,
+		_ => TokenKind::Other,
+	}
+}
+
+/// The number of values a Befunge-93 instruction pops from the stack.
+fn pops(c: char) -> usize {
+	match c {
+		'+' | '-' | '*' | '/' | '%' | '`' | '\\' | 'g' => 2,
+		'!' | '_' | '|' | ':' | '$' | '.' | ',' => 1,
+		'p' => 3,
+		_ => 0,
+	}
+}
+
+/// Apply a single instruction to a copy of the stack and return the result.
+///
+/// The stack is given with the top item last. Only instructions which just
+/// change the stack are applied: the digits and `+-*/%!`, along with `:`,
+/// `\`, `$` and the comparison `` ` ``. Any other instruction, including those
+/// which move the pointer, read input, write output or use the playfield,
+/// returns the stack unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::simulate;
+///
+/// assert_eq!(simulate('+', &[1, 2, 3]), vec![1, 5]);
+/// assert_eq!(simulate(':', &[4]), vec![4, 4]);
+/// assert_eq!(simulate('\\', &[1, 2]), vec![2, 1]);
+/// assert_eq!(simulate('$', &[1, 2]), vec![1]);
+/// assert_eq!(simulate('.', &[1, 2]), vec![1, 2]);
+/// ```
+pub fn simulate(instr: char, stack: &[i64]) -> Vec<i64> {
+	match instr {
+		'0'..='9' | '+' | '-' | '*' | '/' | '%' | '!' | '`' | ':' | '\\' | '$' => {},
+		_ => return stack.to_vec(),
+	}
+
+	let mut interpreter = Interpreter::with_output(Program::new(vec![vec![instr]]), io::sink());
+	interpreter.input = Box::new(io::empty());
+	interpreter.preload_stack(stack);
+	interpreter.process_instruction(instr);
+
+	interpreter.stack.stack
+}
+
+/// The outcome of running a program with its output captured.
+///
+/// This is returned by `Interpreter::execute_with_limit`,
+/// `Interpreter::execute_with_timeout` and `run_many`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExecutionResult {
+	/// Output written by the program.
+	pub output: String,
+	/// Number of instructions processed.
+	pub steps: usize,
+	/// Why the program stopped.
+	pub terminated: TerminationReason,
+}
+
+/// Why a program stopped running.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TerminationReason {
+	/// The program ended at an `@` or other end instruction.
+	Halted,
+	/// The program was stopped by the step limit.
+	StepLimit,
+	/// The program was stopped because it ran for too long.
+	Timeout,
+	/// The program was stopped by an error.
+	Error(BefungeError),
+}
+
+/// Run a program and return its output.
+///
+/// `&` and `~` read -1, as there is no input. A program which has not
+/// ended after a million instructions is stopped, and the output written
+/// so far is returned.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge;
+///
+/// assert_eq!(befunge::run("64+\"!dlroW ,olleH\">:#,_@"), "Hello, World!\n");
+/// assert_eq!(befunge::run("&.~.@"), "-1 -1 ");
+///
+/// // Never ends.
+/// assert!(befunge::run("1.").starts_with("1 1 "));
+/// ```
+pub fn run(source: &str) -> String {
+	let mut interpreter = Interpreter::from_program(Program::from_str(source));
+	interpreter.input = Box::new(io::empty());
+
+	interpreter.execute_with_limit(RUN_MAX_STEPS).output
+}
+
+/// Run a program several times, using a different random seed each time.
+///
+/// This shows the spread of behaviour of programs using `?`. Run `n` uses
+/// the seed `n`, so the results are the same for every call. Each run reads
+/// the same `input` and is stopped after a million instructions if it has
+/// not ended.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{self, TerminationReason};
+///
+/// // Going right prints 1, going left wraps around to the `@`.
+/// let results = befunge::run_many("?1.@", &[], 10);
+///
+/// assert_eq!(results.len(), 10);
+/// assert!(results.iter().all(|result| result.terminated == TerminationReason::Halted));
+/// assert!(results.iter().any(|result| result.output == "1 "));
+/// assert!(results.iter().any(|result| result.output == ""));
+/// ```
+pub fn run_many(source: &str, input: &[i64], runs: usize) -> Vec<ExecutionResult> {
+	let program = Program::from_str(source);
+
+	(0..runs)
+		.map(|run| {
+			let mut interpreter = Interpreter::with_values(program.clone(), input.to_vec());
+			interpreter.input = Box::new(io::empty());
+			interpreter.rng = Box::new(StdRng::from_seed(&[run][..]));
+			interpreter.execute_with_limit(RUN_MAX_STEPS)
+		})
+		.collect()
+}
+
+/// The first step at which two interpreters behaved differently.
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+	/// Number of steps taken by each interpreter, including the differing one.
+	pub step: usize,
+	/// Positions of the two instruction pointers after the step.
+	pub positions: [[usize; 2]; 2],
+	/// Output written by each interpreter up to and including the step.
+	pub outputs: [String; 2],
+}
+
+/// Run two interpreters in lockstep and find the first step where they differ.
+///
+/// After each step the positions of the pointers and the output written so
+/// far are compared, as is whether each program has ended. Runs for at most
+/// `max_steps` steps and returns `None` if no difference was found.
+///
+/// The interpreters should be given the same user values. Programs using `?`
+/// will usually diverge since each interpreter has its own random numbers.
+/// Output sent over a channel is not compared.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{self, Dialect, Interpreter, Program};
+///
+/// // Counts down from 5.
+/// let program = Program::new(vec!["5>:.1-:v".chars().collect(), "@^     _".chars().collect()]);
+///
+/// let mut strict = Interpreter::from_program(program.clone());
+/// let mut extended = Interpreter::from_program(program);
+/// extended.set_dialect(Dialect::Extended);
+///
+/// assert_eq!(befunge::find_divergence(&mut strict, &mut extended, 1000), None);
+/// ```
+pub fn find_divergence(first: &mut Interpreter, second: &mut Interpreter,
+					   max_steps: usize) -> Option<Divergence> {
+	let buffers = [Rc::new(RefCell::new(Vec::new())), Rc::new(RefCell::new(Vec::new()))];
+	let first_output = mem::replace(&mut first.output, Box::new(SharedBuffer(buffers[0].clone())));
+	let second_output = mem::replace(&mut second.output, Box::new(SharedBuffer(buffers[1].clone())));
+
+	let mut divergence = None;
+	for step in 1..max_steps + 1 {
+		let running = first.step();
+		let same = running == second.step()
+			&& first.pos == second.pos
+			&& *buffers[0].borrow() == *buffers[1].borrow();
+
+		if !same {
+			divergence = Some(Divergence {
+				step,
+				positions: [first.pos, second.pos],
+				outputs: [String::from_utf8_lossy(&buffers[0].borrow()).into_owned(),
+						  String::from_utf8_lossy(&buffers[1].borrow()).into_owned()],
+			});
+			break;
+		}
+
+		if running == StepOutcome::Ended {
+			break;
+		}
+	}
+
+	first.output = first_output;
+	second.output = second_output;
+
+	divergence
+}
+
+/// Longest a program may run for in `assert_output`.
+#[cfg(feature = "testutil")]
+const ASSERT_OUTPUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run the source as a program and check it writes the expected output.
+///
+/// The input is given to the program as user values. This requires the
+/// `testutil` feature.
+///
+/// # Panics
+///
+/// Panics, describing the first difference, if the output does not match.
+/// Also panics if the program runs for more than five seconds.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge;
+///
+/// befunge::assert_output("\"!dlroW ,olleH\">:#,_@", &[], "Hello, World!");
+/// befunge::assert_output("&&+.@", &[2, 3], "5 ");
+/// ```
+#[cfg(feature = "testutil")]
+pub fn assert_output(source: &str, input: &[i64], expected: &str) {
+	let program = Program::from_str(source);
+	let mut interpreter = Interpreter::with_values(program, input.to_vec());
+	interpreter.set_input(io::empty());
+
+	let (actual, finished) = interpreter.execute_capture_timeout(ASSERT_OUTPUT_TIMEOUT);
+	if !finished {
+		panic!("program did not finish within {:?}\n  output so far: {:?}",
+			ASSERT_OUTPUT_TIMEOUT, actual);
+	}
+
+	if actual != expected {
+		let first_difference = actual.chars()
+			.zip(expected.chars())
+			.take_while(|&(a, e)| a == e)
+			.count();
+
+		panic!("program output did not match\n  expected: {:?}\n    actual: {:?}\n  \
+				first difference at character {}",
+			expected, actual, first_difference);
+	}
+}
+
+/// The character shown for a cell when rendering, so each cell is one column wide.
+fn visible_char(c: char) -> char {
+	if c == '\t' { TAB_SYMBOL } else { c }
+}
+
+/// Join lines of cells into source text.
+fn join_lines(lines: &[Vec<char>]) -> String {
+	lines.iter()
+		.map(|line| line.iter().collect::<String>())
+		.collect::<Vec<String>>()
+		.join("\n")
+}
+
+/// Errors found in Befunge programs or raised while running them.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BefungeError {
+	/// The program does not fit in the Befunge-93 playfield.
+	TooLarge {
+		/// Number of lines in the program.
+		rows: usize,
+		/// Number of characters in the longest line.
+		cols: usize,
+	},
+	/// A cell holds a character which is not a single byte.
+	InvalidCell {
+		/// Position of the cell.
+		pos: [usize; 2],
+		/// The character in the cell.
+		c: char,
+	},
+	/// The program has no `@` instruction.
+	NoEndInstruction,
+	/// The number of cells does not match the rows and columns given.
+	SizeMismatch {
+		/// Number of rows expected.
+		rows: usize,
+		/// Number of columns expected.
+		cols: usize,
+		/// Number of cells given.
+		len: usize,
+	},
+	/// A position is outside of the program.
+	OutOfBounds {
+		/// The `[row, col]` position.
+		pos: [usize; 2],
+	},
+	/// Writing the program's output failed.
+	OutputFailed {
+		/// The kind of error returned by the writer.
+		kind: io::ErrorKind,
+	},
+	/// The program tried to write more newlines than the interpreter allows.
+	NewlineLimitExceeded {
+		/// The maximum number of newlines.
+		limit: usize,
+	},
+	/// The program tried to make more `p` writes than the interpreter allows.
+	SelfModifyLimitExceeded {
+		/// The maximum number of writes.
+		limit: usize,
+	},
+	/// An instruction popped more values than were on the stack, while
+	/// underflow is treated as an error.
+	StackUnderflow {
+		/// The `[row, col]` position of the instruction.
+		pos: [usize; 2],
+	},
+}
+
+impl fmt::Display for BefungeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			BefungeError::TooLarge { rows, cols } => {
+				write!(f, "program is {}x{} but the playfield is {}x{}",
+					cols, rows, PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT)
+			},
+			BefungeError::InvalidCell { pos, c } => {
+				write!(f, "invalid character {:?} at {:?}", c, pos)
+			},
+			BefungeError::NoEndInstruction => write!(f, "program has no @ instruction"),
+			BefungeError::SizeMismatch { rows, cols, len } => {
+				write!(f, "expected {}x{} cells but found {}", cols, rows, len)
+			},
+			BefungeError::OutOfBounds { pos } => write!(f, "position {:?} is outside of the program", pos),
+			BefungeError::OutputFailed { kind } => write!(f, "failed to write output: {:?}", kind),
+			BefungeError::NewlineLimitExceeded { limit } => {
+				write!(f, "program wrote more than {} newlines", limit)
+			},
+			BefungeError::SelfModifyLimitExceeded { limit } => {
+				write!(f, "program made more than {} self-modifications", limit)
+			},
+			BefungeError::StackUnderflow { pos } => write!(f, "stack underflow at {:?}", pos),
+		}
+	}
+}
+
+impl Error for BefungeError {}
 
 /// The Befunge program stack
 ///
 /// Contains a `Vec` with modified `push` and `pop` functions.
 pub struct Stack {
-	stack: Vec<u32>
+	stack: Vec<i64>
 }
 
 /// Creates an empty stack.
@@ -41,26 +579,62 @@ impl Stack {
 	/// Pops the Befunge stack.
 	///
 	/// Returns 0 if the stack is empty and the top item otherwise.
-	pub fn pop(&mut self) -> u32 {
-		match self.stack.pop() {
-			Some(x) => x,
-			None => 0,
-		}
+	pub fn pop(&mut self) -> i64 {
+		self.stack.pop().unwrap_or_default()
 	}
 
 	/// Pushes a new item to the stack.
-	pub fn push(&mut self, item: u32) {
+	pub fn push(&mut self, item: i64) {
 		self.stack.push(item);
 	}
 
+	/// The top item on the stack, without removing it.
+	///
+	/// Unlike `pop`, returns `None` if the stack is empty.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Stack;
+	///
+	/// let mut stack = Stack::default();
+	/// assert_eq!(stack.peek(), None);
+	/// assert!(stack.is_empty());
+	///
+	/// stack.push(1);
+	/// stack.push(2);
+	///
+	/// assert_eq!(stack.peek(), Some(2));
+	/// assert_eq!(stack.len(), 2);
+	/// assert_eq!(stack.as_slice(), &[1, 2]);
+	/// ```
+	pub fn peek(&self) -> Option<i64> {
+		self.stack.last().cloned()
+	}
+
+	/// Number of items on the stack.
+	pub fn len(&self) -> usize {
+		self.stack.len()
+	}
+
+	/// Whether the stack has no items.
+	pub fn is_empty(&self) -> bool {
+		self.stack.is_empty()
+	}
+
+	/// The items on the stack, with the top item last.
+	pub fn as_slice(&self) -> &[i64] {
+		&self.stack
+	}
+
 	/// Duplicates the top item on the stack.
 	///
 	/// If the stack is empty pushes two zeros to the stack.
 	pub fn duplicate_top(&mut self) {
 		match self.stack.pop() {
 			Some(x) => {
-				self.stack.push(x.clone());
-				self.stack.push(x.clone());
+				self.stack.push(x);
+				self.stack.push(x);
 			},
 			None => {
 				self.stack.push(0);
@@ -69,195 +643,3088 @@ impl Stack {
 		}
 	}
 
-	/// Switches the two items at the top of the stack.
+	/// Switches the two items at the top of the stack.
+	///
+	/// If the stack does not contain two items then zeros
+	/// are pushed to the stack.
+	pub fn switch_top(&mut self) {
+		let a = self.stack.pop();
+
+		if let Some(a) = a {
+			let b = self.stack.pop();
+
+			if let Some(b) = b {
+				// Push in reverse order.
+				self.stack.push(a);
+				self.stack.push(b);
+			} else {
+				// Push a back and a zero.
+				self.stack.push(a);
+				self.stack.push(0);
+			}
+			
+		} else {
+			// Fill stack with two zeros.
+			self.stack.push(0);
+			self.stack.push(0);
+		}
+	}
+}
+
+/// The Befunge program
+///
+/// Contains the instructions, along with a copy of them as
+/// originally given. User values are supplied separately to
+/// the `Interpreter` running the program.
+///
+/// Shorter lines are padded with spaces to the width of the longest
+/// line, so the playfield is always a rectangle and string mode pushes
+/// a space for each padded cell.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{Interpreter, Program};
+///
+/// // A string running down through an empty line and a line of spaces.
+/// let lines = vec!["v", "\"", "", "  ", "\"", "@"];
+/// let program = Program::new(lines.iter().map(|line| line.chars().collect()).collect());
+/// let mut interpreter = Interpreter::from_program(program);
+/// interpreter.record_stack_history(Some(1));
+///
+/// interpreter.execute().unwrap();
+///
+/// assert_eq!(interpreter.stack_history(), &[vec![32, 32]]);
+/// ```
+#[derive(Clone)]
+pub struct Program {
+	instructions: Vec<Vec<char>>,
+	original: Vec<Vec<char>>,
+}
+
+impl Program {
+	/// Create a new Befunge program with the specified instructions.
+	///
+	/// Lines shorter than the longest line are padded with spaces.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let program = Program::new(vec!["v".chars().collect(), "".chars().collect(), ">1.@".chars().collect()]);
+	///
+	/// assert_eq!(program.chars_in_line(0), 4);
+	/// assert_eq!(program.chars_in_line(1), 4);
+	/// assert_eq!(program.chars_in_line(2), 4);
+	/// assert_eq!(program.to_source(), "v   \n    \n>1.@");
+	/// ```
+	pub fn new(mut instructions: Vec<Vec<char>>) -> Program {
+		let width = instructions.iter().map(|line| line.len()).max().unwrap_or(0);
+
+		for line in &mut instructions {
+			line.resize(width, ' ');
+		}
+
+		Program {
+			original: instructions.clone(),
+			instructions,
+		}
+	}
+
+	/// Create a program on the full Befunge-93 playfield of
+	/// `PLAYFIELD_WIDTH` by `PLAYFIELD_HEIGHT` cells.
+	///
+	/// The instructions are padded with spaces, or cut off, to fill the
+	/// playfield exactly, so the pointer always wraps at its edges.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new_standard(vec!["<@".chars().collect()]);
+	///
+	/// assert_eq!(program.lines(), 25);
+	/// assert_eq!(program.chars_in_line(0), 80);
+	///
+	/// // The `<` wraps to column 79 and runs back through the spaces to the `@`.
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// assert_eq!(interpreter.execute_with_limit(100).steps, 80);
+	/// ```
+	pub fn new_standard(mut instructions: Vec<Vec<char>>) -> Program {
+		instructions.resize(PLAYFIELD_HEIGHT, Vec::new());
+		for line in &mut instructions {
+			line.resize(PLAYFIELD_WIDTH, ' ');
+		}
+
+		Program::new(instructions)
+	}
+
+	/// Create a program from source text.
+	///
+	/// Each line of the source is a line of the program, with every
+	/// character a cell. Lines are split on `\n` and exactly one trailing
+	/// `\r` is removed from each, so `\r\n` line endings also work. Spaces and tabs
+	/// are kept as they are, each as a single cell, and blank lines become
+	/// lines of spaces.
+	///
+	/// Empty source gives a program with no cells, which ends as soon as it
+	/// is run. Source made up only of spaces has no `@`, so like any other
+	/// such program it runs forever.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, TerminationReason};
+	///
+	/// let program = Program::from_str("1 .@\r\n  2");
+	///
+	/// assert_eq!(program.lines(), 2);
+	/// assert_eq!(program.chars_in_line(0), 4);
+	/// assert_eq!(program.chars_in_line(1), 4);
+	/// assert_eq!(program.get_instruction_char([0, 1]), ' ');
+	/// assert_eq!(program.get_instruction_char([1, 2]), '2');
+	///
+	/// // Only the `\r` ending the line is removed.
+	/// assert_eq!(Program::from_str("a\rb\r\n").to_source(), "a\rb");
+	/// assert_eq!(Program::from_str("a\r\r\n").to_source(), "a\r");
+	///
+	/// // The pointer moves down through a blank line to the `@`.
+	/// let mut interpreter = Interpreter::from_program(Program::from_str("v\n\n@"));
+	/// assert_eq!(interpreter.execute_with_limit(10).terminated, TerminationReason::Halted);
+	///
+	/// let mut empty = Interpreter::from_program(Program::from_str(""));
+	/// let result = empty.execute_with_limit(10);
+	/// assert_eq!(result.terminated, TerminationReason::Halted);
+	/// assert_eq!(result.steps, 0);
+	///
+	/// let mut blank = Interpreter::from_program(Program::from_str("   \n   "));
+	/// assert_eq!(blank.execute_with_limit(10).terminated, TerminationReason::StepLimit);
+	/// ```
+	#[allow(clippy::should_implement_trait)]
+	pub fn from_str(source: &str) -> Program {
+		let instructions = source.split_terminator('\n')
+			.map(|line| line.strip_suffix('\r').unwrap_or(line).chars().collect())
+			.collect();
+
+		Program::new(instructions)
+	}
+
+	/// Create a program from cells given in row-major order.
+	///
+	/// The number of cells must be exactly `rows * cols`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Program};
+	///
+	/// let program = Program::from_matrix(3, 3, "1 v @ <.:".chars().collect()).unwrap();
+	///
+	/// assert_eq!(program.lines(), 3);
+	/// assert_eq!(program.get(2, 0), 'v');
+	/// assert_eq!(program.get(1, 2), '.');
+	///
+	/// assert_eq!(Program::from_matrix(3, 3, vec![' '; 8]).err(),
+	///            Some(BefungeError::SizeMismatch { rows: 3, cols: 3, len: 8 }));
+	/// ```
+	pub fn from_matrix(rows: usize, cols: usize, cells: Vec<char>) -> Result<Program, BefungeError> {
+		if rows.checked_mul(cols) != Some(cells.len()) {
+			return Err(BefungeError::SizeMismatch { rows, cols, len: cells.len() });
+		}
+
+		let instructions = if cols == 0 {
+			vec![Vec::new(); rows]
+		} else {
+			cells.chunks(cols).map(|row| row.to_vec()).collect()
+		};
+
+		Ok(Program::new(instructions))
+	}
+
+	/// Create a program from the source text in a file.
+	///
+	/// The file is read as UTF-8 and split into lines as by `from_str`, so
+	/// `\r\n` line endings are removed and each tab is a single cell.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	/// use std::fs;
+	///
+	/// let path = std::env::temp_dir().join("rubefunge_93_from_file.bf");
+	/// fs::write(&path, "1\t.@\r\n2\r\n").unwrap();
+	///
+	/// let program = Program::from_file(&path).unwrap();
+	/// fs::remove_file(&path).unwrap();
+	///
+	/// assert_eq!(program.dimensions(), (2, 4));
+	/// assert_eq!(program.get(1, 0), '\t');
+	/// assert_eq!(program.get(3, 0), '@');
+	/// assert_eq!(program.get(1, 1), ' ');
+	/// ```
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Program> {
+		let source = fs::read_to_string(path)?;
+
+		Ok(Program::from_str(&source))
+	}
+
+	/// Create a program from source text read a line at a time.
+	///
+	/// The text must be UTF-8, and is split into lines and padded as by
+	/// `from_str`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	/// use std::io::Cursor;
+	///
+	/// let program = Program::from_reader(Cursor::new(&b"v\r\n>1.@\r\n"[..])).unwrap();
+	///
+	/// assert_eq!(program.dimensions(), (2, 4));
+	/// assert_eq!(program.to_source(), "v   \n>1.@");
+	///
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["1 "]);
+	///
+	/// // Only one `\r` is removed from the end of each line.
+	/// let program = Program::from_reader(Cursor::new(&b"a\r\r\n"[..])).unwrap();
+	/// assert_eq!(program.to_source(), "a\r");
+	/// ```
+	pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Program> {
+		let mut instructions = Vec::new();
+		for line in reader.split(b'\n') {
+			let line = String::from_utf8(line?)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			instructions.push(line.strip_suffix('\r').unwrap_or(&line).chars().collect());
+		}
+
+		Ok(Program::new(instructions))
+	}
+
+	/// Create a program from gzip compressed source text.
+	///
+	/// This requires the `gzip` feature.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # extern crate flate2;
+	/// # extern crate rubefunge_93;
+	/// use flate2::Compression;
+	/// use flate2::write::GzEncoder;
+	/// use rubefunge_93::befunge::Program;
+	/// use std::io::Write;
+	///
+	/// # fn main() {
+	/// let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+	/// encoder.write_all(b"1.@\n2.").unwrap();
+	/// let compressed = encoder.finish().unwrap();
+	///
+	/// let program = Program::from_gzip_reader(&compressed[..]).unwrap();
+	///
+	/// assert_eq!(program.lines(), 2);
+	/// assert_eq!(program.get_instruction_char([0, 2]), '@');
+	/// assert_eq!(program.get_instruction_char([1, 1]), '.');
+	/// # }
+	/// ```
+	#[cfg(feature = "gzip")]
+	pub fn from_gzip_reader<R: ::std::io::Read>(r: R) -> io::Result<Program> {
+		Program::from_reader(io::BufReader::new(::flate2::read::GzDecoder::new(r)))
+	}
+
+	/// Number of lines in the Befunge program.
+	pub fn lines(&self) -> usize {
+		self.instructions.len()
+	}
+
+	/// The number of rows and columns of the program, as `(rows, cols)`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// assert_eq!(Program::from_str("1.@\n2").dimensions(), (2, 3));
+	/// assert_eq!(Program::from_str("").dimensions(), (0, 0));
+	/// ```
+	pub fn dimensions(&self) -> (usize, usize) {
+		(self.lines(), self.instructions.first().map_or(0, |line| line.len()))
+	}
+
+	/// Number of characters in the specified line.
+	pub fn chars_in_line(&self, line: usize) -> usize {
+		self.instructions[line].len()
+	}
+
+	/// Gets the instruction character at the given position.
+	pub fn get_instruction_char(&self, pos: [usize;2]) -> char {
+		self.instructions[pos[0]][pos[1]]
+	}
+
+	/// Sets the instruction character at the given position.
+	pub fn set_instruction_char(&mut self, pos: [usize;2], c: char) {
+		self.instructions[pos[0]][pos[1]] = c;
+	}
+
+	/// Gets the character at column `x` and row `y`.
+	///
+	/// This follows the Befunge convention used by `g` and `p`, and is
+	/// equivalent to `get_instruction_char([y, x])`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let program = Program::new(vec!["ab".chars().collect(), "cd".chars().collect()]);
+	///
+	/// assert_eq!(program.get(1, 0), 'b');
+	/// assert_eq!(program.get(1, 0), program.get_instruction_char([0, 1]));
+	/// ```
+	pub fn get(&self, x: usize, y: usize) -> char {
+		self.get_instruction_char([y, x])
+	}
+
+	/// Sets the character at column `x` and row `y`.
+	///
+	/// This follows the Befunge convention used by `g` and `p`, and is
+	/// equivalent to `set_instruction_char([y, x], c)`.
+	pub fn set(&mut self, x: usize, y: usize, c: char) {
+		self.set_instruction_char([y, x], c);
+	}
+
+	/// The position one cell from `pos` in the given direction.
+	///
+	/// Moving off the edge of a line wraps around to the other side.
+	fn advance(&self, pos: [usize; 2], direction: Direction) -> [usize; 2] {
+		let [row, col] = pos;
+		let width = self.instructions.get(row).map_or(0, |line| line.len());
+
+		match direction {
+			Direction::Right => {
+				if col + 1 >= width {
+					[row, 0]
+				} else {
+					[row, col + 1]
+				}
+			},
+			Direction::Left => {
+				if col == 0 {
+					[row, width.saturating_sub(1)]
+				} else {
+					[row, col - 1]
+				}
+			},
+			Direction::Up => {
+				if row == 0 {
+					[self.lines().saturating_sub(1), col]
+				} else {
+					[row - 1, col]
+				}
+			},
+			Direction::Down => {
+				if row + 1 >= self.lines() {
+					[0, col]
+				} else {
+					[row + 1, col]
+				}
+			},
+		}
+	}
+
+	/// The character at the position, treating cells outside the program as spaces.
+	fn cell(&self, pos: [usize; 2]) -> char {
+		self.instructions.get(pos[0])
+			.and_then(|line| line.get(pos[1]))
+			.cloned()
+			.unwrap_or(' ')
+	}
+
+	/// The cells which can be reached from the start position and direction.
+	///
+	/// This follows every branch of `_`, `|` and `?`, so it may include cells
+	/// which are never reached in practice. Changes made by `p` are not
+	/// considered, so a self-modifying program may reach other cells too.
+	pub fn reachable_cells(&self, start: [usize; 2], dir: Direction) -> HashSet<[usize; 2]> {
+		self.reachable_states(start, dir)
+			.into_iter()
+			.map(|(pos, _, _)| pos)
+			.collect()
+	}
+
+	/// The cells which can be reached outside of string mode, so are run as instructions.
+	fn reachable_instructions(&self, start: [usize; 2], dir: Direction) -> HashSet<[usize; 2]> {
+		self.reachable_states(start, dir)
+			.into_iter()
+			.filter(|&(_, _, state)| state == State::Normal)
+			.map(|(pos, _, _)| pos)
+			.collect()
+	}
+
+	/// Every position, direction and state the pointer can reach.
+	fn reachable_states(&self, start: [usize; 2], dir: Direction) -> HashSet<([usize; 2], Direction, State)> {
+		let mut visited = HashSet::new();
+		let mut pending = vec![(start, dir, State::Normal)];
+
+		while let Some((pos, dir, state)) = pending.pop() {
+			if visited.insert((pos, dir, state)) {
+				pending.extend(self.next_states(pos, dir, state));
+			}
+		}
+
+		visited
+	}
+
+	/// Every position, direction and state the pointer may move to next.
+	fn next_states(&self, pos: [usize; 2], dir: Direction, state: State) -> Vec<([usize; 2], Direction, State)> {
+		let c = self.cell(pos);
+		let go = |dir| (self.advance(pos, dir), dir, State::Normal);
+
+		if state == State::String {
+			let state = if c == '"' { State::Normal } else { State::String };
+			return vec![(self.advance(pos, dir), dir, state)];
+		}
+
+		match c {
+			'@' => vec![],
+			'"' => vec![(self.advance(pos, dir), dir, State::String)],
+			'#' => vec![(self.advance(self.advance(pos, dir), dir), dir, State::Normal)],
+			'>' => vec![go(Direction::Right)],
+			'<' => vec![go(Direction::Left)],
+			'^' => vec![go(Direction::Up)],
+			'v' => vec![go(Direction::Down)],
+			'_' => vec![go(Direction::Right), go(Direction::Left)],
+			'|' => vec![go(Direction::Up), go(Direction::Down)],
+			'?' => vec![go(Direction::Right), go(Direction::Left), go(Direction::Up), go(Direction::Down)],
+			_ => vec![go(dir)],
+		}
+	}
+
+	/// The shortest sequence of cells the pointer can pass through to reach an `@`.
+	///
+	/// The route starts with `start` and ends with the `@`, and skips cells
+	/// jumped over by `#`. This is only a guide: every branch of `_`, `|`
+	/// and `?` is assumed possible, whatever the stack holds or the random
+	/// choices made, and changes made by `p` are not considered. Returns
+	/// `None` if no `@` can be reached.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Program};
+	///
+	/// let program = Program::new(vec!["1v".chars().collect(), " @".chars().collect()]);
+	///
+	/// assert_eq!(program.route_to_end([0, 0], Direction::Right),
+	///            Some(vec![[0, 0], [0, 1], [1, 1]]));
+	/// ```
+	pub fn route_to_end(&self, start: [usize; 2], dir: Direction) -> Option<Vec<[usize; 2]>> {
+		let first = (start, dir, State::Normal);
+		let mut parents: HashMap<_, Option<([usize; 2], Direction, State)>> = HashMap::new();
+		let mut pending = VecDeque::new();
+		parents.insert(first, None);
+		pending.push_back(first);
+
+		while let Some(current) = pending.pop_front() {
+			let (pos, _, state) = current;
+			if state == State::Normal && self.cell(pos) == '@' {
+				let mut route = vec![pos];
+				let mut previous = parents[&current];
+				while let Some(parent) = previous {
+					route.push(parent.0);
+					previous = parents[&parent];
+				}
+				route.reverse();
+				return Some(route);
+			}
+
+			for next in self.next_states(pos, current.1, state) {
+				if let Entry::Vacant(entry) = parents.entry(next) {
+					entry.insert(Some(current));
+					pending.push_back(next);
+				}
+			}
+		}
+
+		None
+	}
+
+	/// Whether an `@` instruction can be reached from the start position and direction.
+	///
+	/// Unlike `has_end_instruction` this catches an `@` which exists but can
+	/// never be reached. It uses `reachable_cells`, so branches which are never
+	/// taken in practice and changes made by `p` can make the answer wrong.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Program};
+	///
+	/// // The pointer loops around the arrows and never reaches the `@`.
+	/// let program = Program::new(vec![">v@".chars().collect(), "^<".chars().collect()]);
+	///
+	/// assert!(program.has_end_instruction());
+	/// assert!(!program.has_reachable_end([0, 0], Direction::Right));
+	/// ```
+	pub fn has_reachable_end(&self, start: [usize; 2], dir: Direction) -> bool {
+		self.reachable_instructions(start, dir)
+			.into_iter()
+			.any(|pos| self.cell(pos) == '@')
+	}
+
+	/// Every `@` in the program, in reading order, and whether it can be reached.
+	///
+	/// Reachability starts from the top left moving right, as with
+	/// `has_reachable_end`. An `@` which is only passed over in string mode
+	/// is not counted as reachable.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let program = Program::new(vec!["1.v @".chars().collect(), "  @".chars().collect()]);
+	///
+	/// assert_eq!(program.end_points(), vec![([0, 4], false), ([1, 2], true)]);
+	/// ```
+	pub fn end_points(&self) -> Vec<([usize; 2], bool)> {
+		let reachable = self.reachable_instructions([0, 0], Direction::Right);
+		let mut end_points = Vec::new();
+
+		for (row, line) in self.instructions.iter().enumerate() {
+			for (col, &c) in line.iter().enumerate() {
+				if c == '@' {
+					end_points.push(([row, col], reachable.contains(&[row, col])));
+				}
+			}
+		}
+
+		end_points
+	}
+
+	/// A copy of the program with unreachable instructions replaced by spaces.
+	///
+	/// Reachability starts from the top left moving right and follows every
+	/// branch of `_`, `|` and `?`, so nothing which may run is removed. A
+	/// reachable `g` or `p` could read or rewrite any cell, so in that case
+	/// the program is returned unchanged.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let program = Program::new(vec!["1.v 9".chars().collect(), "8 @ 7".chars().collect()]);
+	///
+	/// assert_eq!(program.strip_unreachable().to_source(), "1.v  \n  @  ");
+	/// ```
+	pub fn strip_unreachable(&self) -> Program {
+		let reachable = self.reachable_cells([0, 0], Direction::Right);
+		let instructions = self.reachable_instructions([0, 0], Direction::Right);
+
+		if instructions.iter().any(|pos| self.cell(*pos) == 'g' || self.cell(*pos) == 'p') {
+			return self.clone();
+		}
+
+		let instructions = self.instructions.iter()
+			.enumerate()
+			.map(|(row, line)| line.iter()
+				.enumerate()
+				.map(|(col, c)| if reachable.contains(&[row, col]) { *c } else { ' ' })
+				.collect())
+			.collect();
+
+		Program::new(instructions)
+	}
+
+	/// Whether the program can run an `&` or `~` instruction to read input.
+	///
+	/// This uses the cells reachable from the top left moving right, see
+	/// `reachable_cells`. Characters inside strings are not counted.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let cat = Program::new(vec!["~:1+!#@_,".chars().collect()]);
+	/// let constant = Program::new(vec!["\"~&\"1.@".chars().collect()]);
+	///
+	/// assert!(cat.uses_input());
+	/// assert!(!constant.uses_input());
+	/// ```
+	pub fn uses_input(&self) -> bool {
+		self.reachable_instructions([0, 0], Direction::Right)
+			.into_iter()
+			.any(|pos| self.cell(pos) == '&' || self.cell(pos) == '~')
+	}
+
+	/// Whether column `x` and row `y` is a cell of the program.
+	fn contains(&self, x: usize, y: usize) -> bool {
+		y < self.lines() && x < self.chars_in_line(y)
+	}
+
+	/// The current instructions as source text, with lines separated by `\n`.
+	pub fn to_source(&self) -> String {
+		join_lines(&self.instructions)
+	}
+
+	/// The instructions as they were before any changes made by `p`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Program;
+	///
+	/// let mut program = Program::new(vec!["1.@".chars().collect(), "ab".chars().collect()]);
+	/// program.set(1, 1, 'c');
+	///
+	/// assert_eq!(program.original_source(), "1.@\nab ");
+	/// assert_eq!(program.to_source(), "1.@\nac ");
+	/// ```
+	pub fn original_source(&self) -> String {
+		join_lines(&self.original)
+	}
+
+	/// The `[row, col]` positions of cells which differ from the original program.
+	///
+	/// A cell overwritten with a space counts as modified, while a cell
+	/// written back to its original character does not.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Writes a space over the second `.`, so the 2 is never printed.
+	/// let program = Program::new(vec!["84*90p1.2.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	///
+	/// assert_eq!(output, "1 ");
+	/// assert_eq!(interpreter.program().get_instruction_char([0, 9]), ' ');
+	/// assert_eq!(interpreter.program().modified_cells(), vec![[0, 9]]);
+	/// ```
+	pub fn modified_cells(&self) -> Vec<[usize; 2]> {
+		let mut cells = Vec::new();
+		for (row, line) in self.instructions.iter().enumerate() {
+			for (col, &c) in line.iter().enumerate() {
+				let original = self.original.get(row).and_then(|line| line.get(col));
+				if original != Some(&c) {
+					cells.push([row, col]);
+				}
+			}
+		}
+		cells
+	}
+
+	/// Whether the program contains an `@` instruction.
+	pub fn has_end_instruction(&self) -> bool {
+		self.instructions.iter().any(|line| line.contains(&'@'))
+	}
+
+	/// Check the program is valid without running it.
+	///
+	/// The program must fit in the 80x25 playfield and each cell must hold
+	/// a single byte. If `require_end` is set there must also be an `@`
+	/// instruction; pass `false` for a program which is expected to run
+	/// forever.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Program};
+	///
+	/// let valid = Program::new(vec!["1.@".chars().collect()]);
+	/// assert_eq!(valid.validate(true), Ok(()));
+	///
+	/// let oversized = Program::new(vec![vec!['@'; 81]]);
+	/// assert_eq!(oversized.validate(true), Err(BefungeError::TooLarge { rows: 1, cols: 81 }));
+	///
+	/// let endless = Program::new(vec!["1.".chars().collect()]);
+	/// assert_eq!(endless.validate(true), Err(BefungeError::NoEndInstruction));
+	/// assert_eq!(endless.validate(false), Ok(()));
+	/// ```
+	pub fn validate(&self, require_end: bool) -> Result<(), BefungeError> {
+		let rows = self.lines();
+		let cols = self.instructions.iter().map(|line| line.len()).max().unwrap_or(0);
+
+		if rows > PLAYFIELD_HEIGHT || cols > PLAYFIELD_WIDTH {
+			return Err(BefungeError::TooLarge { rows, cols });
+		}
+
+		for (row, line) in self.instructions.iter().enumerate() {
+			for (col, c) in line.iter().enumerate() {
+				if *c as u32 > 255 {
+					return Err(BefungeError::InvalidCell { pos: [row, col], c: *c });
+				}
+			}
+		}
+
+		if require_end && !self.has_end_instruction() {
+			return Err(BefungeError::NoEndInstruction);
+		}
+
+		Ok(())
+	}
+
+	/// The set of Befunge instructions present in the program.
+	///
+	/// Characters which are not instructions, such as spaces, are ignored.
+	pub fn instruction_set(&self) -> BTreeSet<char> {
+		self.instructions.iter()
+			.flat_map(|line| line.iter())
+			.filter(|c| INSTRUCTIONS.contains(**c))
+			.cloned()
+			.collect()
+	}
+
+	/// Summary counts giving a rough idea of how hard the program is to follow.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Complexity, Program};
+	///
+	/// // The Sieve of Eratosthenes
+	/// let program = Program::new(vec![
+	///     "2>:3g\" \"-!v\\  g30          <".chars().collect(),
+	///     " |!`\"O\":+1_:.:03p>03g+:\"O\"`|".chars().collect(),
+	///     " @               ^  p3\\\" \":<".chars().collect(),
+	///     "2 234567890123456789012345678901234567890123456789012345678901234567890123456789".chars().collect(),
+	/// ]);
+	///
+	/// assert_eq!(program.complexity(), Complexity {
+	///     instructions: 128,
+	///     direction_changes: 9,
+	///     self_modification: 5,
+	///     density: 0.40625,
+	/// });
+	/// ```
+	pub fn complexity(&self) -> Complexity {
+		let mut complexity = Complexity { instructions: 0, direction_changes: 0, self_modification: 0, density: 0.0 };
+		let mut filled = 0;
+
+		for &c in self.instructions.iter().flat_map(|line| line.iter()) {
+			if c != ' ' {
+				filled += 1;
+			}
+			if INSTRUCTIONS.contains(c) {
+				complexity.instructions += 1;
+			}
+			match c {
+				'>' | '<' | '^' | 'v' | '_' | '|' | '?' => complexity.direction_changes += 1,
+				'p' | 'g' => complexity.self_modification += 1,
+				_ => {},
+			}
+		}
+
+		let width = self.instructions.iter().map(|line| line.len()).max().unwrap_or(0);
+		let area = width * self.instructions.len();
+		if area > 0 {
+			complexity.density = filled as f64 / area as f64;
+		}
+
+		complexity
+	}
+}
+
+/// Writes the current instructions as by `Program::to_source`.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::Program;
+///
+/// let mut program = Program::from_str("1.@\n2");
+/// program.set(0, 1, '3');
+///
+/// assert_eq!(program.to_string(), "1.@\n3  ");
+/// ```
+impl fmt::Display for Program {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.to_source())
+	}
+}
+
+/// Static measurements of a program, from `Program::complexity`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Complexity {
+	/// Number of cells holding a Befunge instruction.
+	pub instructions: usize,
+	/// Number of `>`, `<`, `^`, `v`, `_`, `|` and `?` instructions.
+	pub direction_changes: usize,
+	/// Number of `p` and `g` instructions.
+	pub self_modification: usize,
+	/// Fraction of the bounding rectangle which is not a space.
+	pub density: f64,
+}
+
+/// Direction for the instruction pointer.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Direction {
+	/// Moving up.
+	Up,
+	/// Moving down.
+	Down,
+	/// Moving left.
+	Left,
+	/// Moving right.
+	Right,
+}
+
+impl Direction {
+	/// The opposite direction.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Direction;
+	///
+	/// assert_eq!(Direction::Up.reverse(), Direction::Down);
+	/// assert_eq!(Direction::Down.reverse(), Direction::Up);
+	/// assert_eq!(Direction::Left.reverse(), Direction::Right);
+	/// assert_eq!(Direction::Right.reverse(), Direction::Left);
+	/// ```
+	pub fn reverse(self) -> Direction {
+		match self {
+			Direction::Up => Direction::Down,
+			Direction::Down => Direction::Up,
+			Direction::Left => Direction::Right,
+			Direction::Right => Direction::Left,
+		}
+	}
+
+	/// The direction a quarter turn anticlockwise.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Direction;
+	///
+	/// assert_eq!(Direction::Up.turn_left(), Direction::Left);
+	/// assert_eq!(Direction::Left.turn_left(), Direction::Down);
+	/// assert_eq!(Direction::Down.turn_left(), Direction::Right);
+	/// assert_eq!(Direction::Right.turn_left(), Direction::Up);
+	/// ```
+	pub fn turn_left(self) -> Direction {
+		match self {
+			Direction::Up => Direction::Left,
+			Direction::Left => Direction::Down,
+			Direction::Down => Direction::Right,
+			Direction::Right => Direction::Up,
+		}
+	}
+
+	/// The direction a quarter turn clockwise.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::Direction;
+	///
+	/// assert_eq!(Direction::Up.turn_right(), Direction::Right);
+	/// assert_eq!(Direction::Right.turn_right(), Direction::Down);
+	/// assert_eq!(Direction::Down.turn_right(), Direction::Left);
+	/// assert_eq!(Direction::Left.turn_right(), Direction::Up);
+	/// ```
+	pub fn turn_right(self) -> Direction {
+		match self {
+			Direction::Up => Direction::Right,
+			Direction::Right => Direction::Down,
+			Direction::Down => Direction::Left,
+			Direction::Left => Direction::Up,
+		}
+	}
+}
+
+/// Current state of the interpreter.
+///
+/// Each `"` toggles string mode, so back-to-back quotes leave string mode
+/// and immediately enter it again without pushing anything.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{Interpreter, Program};
+///
+/// let program = Program::new(vec!["\"a\"\"b\"@".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+/// interpreter.record_stack_history(Some(1));
+///
+/// interpreter.execute().unwrap();
+///
+/// assert_eq!(interpreter.stack_history(), &[vec!['a' as i64, 'b' as i64]]);
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum State {
+	/// Normal mode.
+	Normal,
+	/// String capture mode.
+	String,
+}
+
+/// Actions to be taken by the interpreter.
+#[derive(PartialEq)]
+pub enum Action {
+	/// Change direction of pointer movement.
+	ChangeDir(Direction),
+	/// Change the state of the interpreter.
+	ChangeState(State),
+	/// Skip the next instruction character.
+	Trampoline,
+	/// Take no action.
+	None,
+	/// End the program.
+	End,
+}
+
+/// Whether the program is still running after a step.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StepOutcome {
+	/// The program has more instructions to run.
+	Running,
+	/// The program has ended.
+	Ended,
+}
+
+/// Measurements taken while running a program.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Metrics {
+	/// Number of instructions processed, including the final `@`.
+	pub steps: usize,
+	/// Number of user values read by `&`.
+	pub integers_read: usize,
+	/// Number of user values read by `~`.
+	pub chars_read: usize,
+	/// Number of newlines written by `,`.
+	pub newlines: usize,
+	/// Number of distinct cells written by `p`.
+	pub cells_modified: usize,
+}
+
+/// Instruction coverage of a program run.
+#[derive(Debug, PartialEq)]
+pub struct Coverage {
+	/// Instructions in the program which were executed.
+	pub executed: BTreeSet<char>,
+	/// Instructions in the program which were never reached.
+	pub unexecuted: BTreeSet<char>,
+}
+
+/// How `,` writes the value it pops.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CharMode {
+	/// Write the value as a Unicode character.
+	Unicode,
+	/// Write the lowest byte of the value.
+	Byte,
+}
+
+/// How output is formatted by the interpreter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct OutputFormat {
+	/// How `,` writes the value it pops.
+	pub char_mode: CharMode,
+	/// Written by `,` in place of values which are not valid characters.
+	pub replacement: char,
+	/// Minimum width of integers written by `.`.
+	pub width: usize,
+	/// Used to pad integers written by `.` to the minimum width.
+	pub pad: char,
+}
+
+/// Writes Unicode characters, replacing invalid ones with U+FFFD,
+/// and integers without padding.
+impl Default for OutputFormat {
+	fn default() -> OutputFormat {
+		OutputFormat {
+			char_mode: CharMode::Unicode,
+			replacement: char::REPLACEMENT_CHARACTER,
+			width: 0,
+			pad: ' ',
+		}
+	}
+}
+
+impl OutputFormat {
+	/// Format an integer written by `.`, padding it on the left.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::OutputFormat;
+	///
+	/// let format = OutputFormat { width: 3, pad: '0', ..OutputFormat::default() };
+	///
+	/// assert_eq!(format.integer(5), "005");
+	/// assert_eq!(format.integer(-5), "-05");
+	/// assert_eq!(format.integer(1234), "1234");
+	/// ```
+	///
+	/// The width includes any minus sign. Zero padding goes after the sign.
+	pub fn integer(&self, value: i64) -> String {
+		let digits = value.to_string();
+		let padding: String = (digits.len()..self.width).map(|_| self.pad).collect();
+
+		if value < 0 && self.pad == '0' {
+			format!("-{}{}", padding, &digits[1..])
+		} else {
+			padding + &digits
+		}
+	}
+}
+
+/// How values on the stack are shown when debugging.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum StackFormat {
+	/// Decimal numbers.
+	#[default]
+	Decimal,
+	/// Hexadecimal numbers, without a prefix.
+	Hex,
+	/// Unicode characters.
+	///
+	/// Values which are not valid characters are shown as U+FFFD.
+	Char,
+}
+
+impl StackFormat {
+	/// Format a single stack value.
+	///
+	/// Negative values are shown in hexadecimal with a minus sign.
+	pub fn value(&self, value: i64) -> String {
+		match *self {
+			StackFormat::Decimal => value.to_string(),
+			StackFormat::Hex if value < 0 => format!("-{:x}", value.unsigned_abs()),
+			StackFormat::Hex => format!("{:x}", value),
+			StackFormat::Char => to_char(value).unwrap_or(char::REPLACEMENT_CHARACTER).to_string(),
+		}
+	}
+}
+
+/// The operands and result of an arithmetic instruction.
+///
+/// For `b a -` the left operand is `b` and the right operand is `a`, so
+/// the result is always `left op right`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ArithmeticEvent {
+	/// The instruction: `+`, `-`, `*`, `/` or `%`.
+	pub op: char,
+	/// The second value popped.
+	pub left: i64,
+	/// The first value popped, from the top of the stack.
+	pub right: i64,
+	/// The value pushed.
+	pub result: i64,
+}
+
+/// An instruction processed by the interpreter.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TraceEvent {
+	/// The `[row, col]` position of the instruction.
+	pub pos: [usize; 2],
+	/// The instruction character.
+	pub instruction: char,
+	/// The direction of the pointer when the instruction was processed.
+	pub direction: Direction,
+	/// The size of the stack after the instruction was processed.
+	pub stack_len: usize,
+}
+
+/// An iterator over the instructions processed by a running program.
+///
+/// This is created by `Interpreter::states`.
+pub struct States<'a> {
+	interpreter: &'a mut Interpreter,
+	ended: bool,
+}
+
+impl<'a> Iterator for States<'a> {
+	type Item = TraceEvent;
+
+	fn next(&mut self) -> Option<TraceEvent> {
+		if self.ended {
+			return None;
+		}
+
+		let (outcome, event) = self.interpreter.step_traced();
+		self.ended = outcome == StepOutcome::Ended;
+		event
+	}
+}
+
+/// A division or modulo by zero.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DivisionByZero {
+	/// The instruction: `/` or `%`.
+	pub op: char,
+	/// The value which was divided by zero.
+	pub dividend: i64,
+	/// The `[row, col]` position of the instruction.
+	pub pos: [usize; 2],
+}
+
+/// Output produced by the program.
+///
+/// Output from `.` and `,` is written in the order it is produced. An
+/// integer is followed by a single space, while a character is written on
+/// its own.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{Interpreter, Program};
+///
+/// let program = Program::new(vec!["\"!\"7.,8.@".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// let output = interpreter.execute_with_limit(1000).output;
+/// assert_eq!(output, "7 !8 ");
+///
+/// let program = Program::from_str("\"!dlroW olleH\">:#,_@");
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// let output = interpreter.execute_with_limit(1000).output;
+/// assert_eq!(output, "Hello World!");
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputEvent {
+	/// An integer written by `.`.
+	Integer(i64),
+	/// A character written by `,`.
+	Char(char),
+	/// A byte written by `,` when using `CharMode::Byte`.
+	Byte(u8),
+}
+
+/// The set of instructions understood by the interpreter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Dialect {
+	/// Only the Befunge-93 instructions.
+	Befunge93,
+	/// Befunge-93 along with extensions from later versions of Befunge.
+	///
+	/// These are `r`, which reverses the direction of the pointer, `[` and
+	/// `]`, which turn it a quarter turn anticlockwise and clockwise, and
+	/// unknown instructions, which also reverse it rather than doing nothing.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Dialect, Direction, Interpreter, Program};
+	///
+	/// // In the extended dialect `x` sends the pointer back, printing the
+	/// // empty stack and wrapping around to the `@`.
+	/// let program = Program::new(vec!["1.x2.@".chars().collect()]);
+	///
+	/// let mut strict = Interpreter::from_program(program.clone());
+	/// let mut extended = Interpreter::from_program(program);
+	/// extended.set_dialect(Dialect::Extended);
+	///
+	/// assert_eq!(strict.execute_with_limit(1000).output, "1 2 ");
+	/// assert_eq!(extended.execute_with_limit(1000).output, "1 0 ");
+	///
+	/// let turns = [(Direction::Up, Direction::Left, Direction::Right),
+	///              (Direction::Left, Direction::Down, Direction::Up),
+	///              (Direction::Down, Direction::Right, Direction::Left),
+	///              (Direction::Right, Direction::Up, Direction::Down)];
+	///
+	/// for &(from, left, right) in &turns {
+	///     for &(instruction, expected) in &[('[', left), (']', right)] {
+	///         let program = Program::new(vec![vec![instruction]]);
+	///         let mut interpreter = Interpreter::from_program(program);
+	///         interpreter.set_dialect(Dialect::Extended);
+	///         interpreter.set_direction(from);
+	///
+	///         interpreter.step();
+	///         assert_eq!(interpreter.direction(), expected);
+	///     }
+	/// }
+	/// ```
+	Extended,
+}
+
+/// Warnings raised by the interpreter while running a program.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Warning {
+	/// The same cell was written by `p` many times in a row,
+	/// which may indicate a loop that never terminates.
+	SelfModifyLoop {
+		/// Position of the cell being rewritten.
+		pos: [usize; 2],
+		/// Number of consecutive writes to the cell.
+		writes: usize,
+	},
+}
+
+/// A source of time for timeouts.
+///
+/// The interpreter uses a `SystemClock` by default. Supplying another clock
+/// with `Interpreter::set_clock` makes timeouts reproducible.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{Clock, Interpreter, Program};
+/// use std::time::Duration;
+///
+/// // A clock which moves on by one millisecond each time it is read.
+/// struct StepClock(Duration);
+///
+/// impl Clock for StepClock {
+///     fn elapsed(&mut self) -> Duration {
+///         let now = self.0;
+///         self.0 += Duration::from_millis(1);
+///         now
+///     }
+/// }
+///
+/// let program = Program::new(vec!["1.".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+/// interpreter.set_clock(StepClock(Duration::from_millis(0)));
+///
+/// // The clock is read once to set the deadline and then every thousand
+/// // steps, so it reaches the deadline on its sixth read.
+/// let (output, finished) = interpreter.execute_capture_timeout(Duration::from_millis(5));
+///
+/// assert!(!finished);
+/// assert_eq!(interpreter.metrics().steps, 5000);
+/// assert_eq!(output, "1 ".repeat(2500));
+/// ```
+pub trait Clock {
+	/// The time elapsed since some fixed starting point.
+	fn elapsed(&mut self) -> Duration;
+}
+
+/// A `Clock` which reads the system's monotonic clock.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+	start: Instant,
+}
+
+impl SystemClock {
+	/// Create a clock which starts now.
+	pub fn new() -> SystemClock {
+		SystemClock { start: Instant::now() }
+	}
+}
+
+impl Default for SystemClock {
+	fn default() -> SystemClock {
+		SystemClock::new()
+	}
+}
+
+impl Clock for SystemClock {
+	fn elapsed(&mut self) -> Duration {
+		self.start.elapsed()
+	}
+}
+
+/// A hook which may replace the instruction about to run.
+type InstructionHook = dyn FnMut([usize; 2], char) -> Option<char>;
+
+/// The Befunge interpreter.
+///
+/// Stack values are signed 64-bit integers. Arithmetic wraps on overflow,
+/// and division and modulo by zero push 0 instead of stopping the program.
+///
+/// `g` and `p` pop the row `y` and then the column `x`, so the column is
+/// pushed first. `p` stores `char::REPLACEMENT_CHARACTER` for values which
+/// are not valid characters, such as the surrogate `0xD800`. Coordinates
+/// outside of the program never change its size: `g` pushes 0 for them and
+/// `p` does nothing. See `set_strict` to also treat coordinates outside of
+/// the 80x25 playfield this way.
+///
+/// # Examples
+///
+/// ```
+/// use rubefunge_93::befunge::{Interpreter, Program};
+///
+/// let program = Program::new(vec!["50/50%@".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+/// interpreter.record_stack_history(Some(1));
+///
+/// assert_eq!(interpreter.execute(), Ok(()));
+/// assert_eq!(interpreter.stack_history(), &[vec![0, 0]]);
+///
+/// let program = Program::new(vec!["35-.@".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// assert_eq!(interpreter.execute_capture_lines(), vec!["-2 "]);
+///
+/// // Put 'X' at column 4 of row 1 and read it back.
+/// let program = Program::new(vec!["\"X\"41p41g,@".chars().collect(), "".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// assert_eq!(interpreter.execute_capture_lines(), vec!["X"]);
+/// assert_eq!(interpreter.program().get(4, 1), 'X');
+/// assert_eq!(interpreter.program().get(4, 0), '1');
+///
+/// // Put 2048 * 27 = 0xD800 at column 0 of row 1.
+/// let program = Program::new(vec!["88*8*4*93**01p@".chars().collect(), "".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// interpreter.execute().unwrap();
+/// assert_eq!(interpreter.program().get(0, 1), char::REPLACEMENT_CHARACTER);
+///
+/// // Get from x = 200, then put at y = 999.
+/// let program = Program::new(vec!["91+:*2*0g.7091+:*:*1-p@".chars().collect()]);
+/// let mut interpreter = Interpreter::from_program(program);
+///
+/// assert_eq!(interpreter.execute_capture_lines(), vec!["0 "]);
+/// assert_eq!(interpreter.program().lines(), 1);
+/// assert_eq!(interpreter.program().chars_in_line(0), 23);
+/// ```
+pub struct Interpreter {
+	stack: Stack,
+	direction: Direction,
+	state: State,
+	pos: [usize; 2],
+	program: Program,
+	values: VecDeque<i64>,
+	metrics: Metrics,
+	histogram: HashMap<char, usize>,
+	cell_counts: Vec<Vec<usize>>,
+	output: Box<dyn Write>,
+	dialect: Dialect,
+	end_instructions: HashSet<char>,
+	format: OutputFormat,
+	char_table: HashMap<i64, char>,
+	newline: Option<String>,
+	echo_input: bool,
+	output_channel: Option<Sender<OutputEvent>>,
+	last_output: Option<OutputEvent>,
+	halt_on_first_output: bool,
+	p_loop_threshold: Option<usize>,
+	last_write: Option<([usize; 2], usize)>,
+	last_modification: Option<([usize; 2], char, char)>,
+	patches: Vec<([usize; 2], char, char)>,
+	stack_history_limit: Option<usize>,
+	stack_history: VecDeque<Vec<i64>>,
+	warnings: Vec<Warning>,
+	direction_hook: Option<Box<dyn FnMut(Direction, Direction)>>,
+	instruction_hook: Option<Box<InstructionHook>>,
+	arithmetic_hook: Option<Box<dyn FnMut(ArithmeticEvent)>>,
+	trace_hook: Option<Box<dyn FnMut(TraceEvent)>>,
+	division_hook: Option<Box<dyn FnMut(DivisionByZero)>>,
+	clock: Box<dyn Clock>,
+	max_self_modifications: Option<usize>,
+	self_modifications: usize,
+	modified_cells: HashSet<[usize; 2]>,
+	error: Option<BefungeError>,
+	stack_format: StackFormat,
+	input: Box<dyn BufRead>,
+	max_newlines: Option<usize>,
+	input_exhausted: bool,
+	rng: Box<dyn Rng>,
+	frozen_grid: bool,
+	underflow_is_error: bool,
+	strict: bool,
+}
+
+/// A writer which collects output into a shared buffer.
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.borrow_mut().extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Interpreter {
+	/// Create a new interpreter from the given program.
+	pub fn from_program(program: Program) -> Interpreter {
+		Interpreter::with_values(program, Vec::new())
+	}
+
+	/// Create a new interpreter which writes the program's output to the writer.
+	///
+	/// Output from `.`, `,` and any echoed input goes to the writer instead
+	/// of standard output. If a write fails the program ends and
+	/// `execute` returns `BefungeError::OutputFailed`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program};
+	/// use std::io::{self, Write};
+	///
+	/// struct Broken;
+	///
+	/// impl Write for Broken {
+	///     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+	///         Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+	///     }
+	///
+	///     fn flush(&mut self) -> io::Result<()> {
+	///         Ok(())
+	///     }
+	/// }
+	///
+	/// let program = Program::from_str("1.2.@");
+	/// let mut interpreter = Interpreter::with_output(program, Broken);
+	///
+	/// assert_eq!(interpreter.execute(),
+	///            Err(BefungeError::OutputFailed { kind: io::ErrorKind::BrokenPipe }));
+	/// assert_eq!(interpreter.metrics().steps, 2);
+	/// ```
+	pub fn with_output<W: Write + 'static>(program: Program, writer: W) -> Interpreter {
+		let mut interpreter = Interpreter::from_program(program);
+		interpreter.output = Box::new(writer);
+		interpreter
+	}
+
+	/// Create a new interpreter which draws the directions taken by `?`
+	/// from the given random number generator.
+	///
+	/// Interpreters created in other ways use `thread_rng`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # extern crate rand;
+	/// # extern crate rubefunge_93;
+	/// use rand::{SeedableRng, StdRng};
+	/// use rubefunge_93::befunge::{Direction, Interpreter, Program};
+	///
+	/// # fn main() {
+	/// let directions = |seed: usize| -> Vec<Direction> {
+	///     let program = Program::from_str("?");
+	///     let mut interpreter = Interpreter::with_rng(program, StdRng::from_seed(&[seed][..]));
+	///
+	///     (0..8).map(|_| {
+	///         interpreter.step();
+	///         interpreter.direction()
+	///     }).collect()
+	/// };
+	///
+	/// // The same seed always takes the same path.
+	/// assert_eq!(directions(7), [Direction::Left, Direction::Right, Direction::Left,
+	///                            Direction::Left, Direction::Right, Direction::Down,
+	///                            Direction::Right, Direction::Left]);
+	/// # }
+	/// ```
+	pub fn with_rng<R: Rng + 'static>(program: Program, rng: R) -> Interpreter {
+		let mut interpreter = Interpreter::from_program(program);
+		interpreter.rng = Box::new(rng);
+		interpreter
+	}
+
+	/// Create a new interpreter from the given program and user values.
+	///
+	/// The values are consumed front-to-back, so the first value
+	/// in the `Vec` is the first one read by the program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Reads a value and prints it.
+	/// let program = Program::new(vec!["&.@".chars().collect()]);
+	///
+	/// let mut first = Interpreter::with_values(program.clone(), vec![1]);
+	/// let mut second = Interpreter::with_values(program, vec![2]);
+	///
+	/// assert_eq!(first.execute_with_limit(1000).output, "1 ");
+	/// assert_eq!(second.execute_with_limit(1000).output, "2 ");
+	/// ```
+	pub fn with_values(program: Program, values: Vec<i64>) -> Interpreter {
+		let (rows, cols) = program.dimensions();
+
+		Interpreter {
+			stack: Stack::default(),
+			direction: Direction::Right,
+			state: State::Normal,
+			pos: [0,0],
+			program,
+			values: VecDeque::from(values),
+			metrics: Metrics::default(),
+			histogram: HashMap::new(),
+			cell_counts: vec![vec![0; cols]; rows],
+			output: Box::new(io::stdout()),
+			dialect: Dialect::Befunge93,
+			end_instructions: HashSet::new(),
+			format: OutputFormat::default(),
+			char_table: HashMap::new(),
+			newline: None,
+			echo_input: false,
+			output_channel: None,
+			last_output: None,
+			halt_on_first_output: false,
+			p_loop_threshold: None,
+			last_write: None,
+			last_modification: None,
+			patches: Vec::new(),
+			stack_history_limit: None,
+			stack_history: VecDeque::new(),
+			warnings: Vec::new(),
+			direction_hook: None,
+			instruction_hook: None,
+			arithmetic_hook: None,
+			trace_hook: None,
+			division_hook: None,
+			clock: Box::new(SystemClock::new()),
+			max_self_modifications: None,
+			self_modifications: 0,
+			modified_cells: HashSet::new(),
+			error: None,
+			stack_format: StackFormat::default(),
+			input: Box::new(io::BufReader::new(io::stdin())),
+			max_newlines: None,
+			input_exhausted: false,
+			rng: Box::new(thread_rng()),
+			frozen_grid: false,
+			underflow_is_error: false,
+			strict: false,
+		}
+	}
+
+	/// Set the instructions understood by the interpreter.
+	///
+	/// Only Befunge-93 instructions are understood by default.
+	pub fn set_dialect(&mut self, dialect: Dialect) {
+		self.dialect = dialect;
+	}
+
+	/// Push the values onto the stack, in order, before execution.
+	///
+	/// The last value ends up on top of the stack.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["-.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.preload_stack(&[5, 3]);
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "2 ");
+	/// ```
+	pub fn preload_stack(&mut self, values: &[i64]) {
+		for &value in values {
+			self.stack.push(value);
+		}
+	}
+
+	/// Move the pointer to the `[row, col]` position.
+	///
+	/// The pointer starts at `[0, 0]`. Returns `BefungeError::OutOfBounds` if
+	/// the position is outside of the program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Direction, Interpreter, Program};
+	///
+	/// // Test `\` on its own.
+	/// let program = Program::new(vec!["@1\\".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_position([0, 2]).unwrap();
+	/// interpreter.set_direction(Direction::Left);
+	/// interpreter.preload_stack(&[1, 2]);
+	///
+	/// interpreter.step();
+	/// assert_eq!(interpreter.stack().as_slice(), &[2, 1]);
+	/// assert_eq!(interpreter.position(), [0, 1]);
+	///
+	/// assert_eq!(interpreter.set_position([1, 0]), Err(BefungeError::OutOfBounds { pos: [1, 0] }));
+	/// ```
+	pub fn set_position(&mut self, pos: [usize; 2]) -> Result<(), BefungeError> {
+		if !self.program.contains(pos[1], pos[0]) {
+			return Err(BefungeError::OutOfBounds { pos });
+		}
+
+		self.pos = pos;
+		Ok(())
+	}
+
+	/// Set the direction the pointer moves in.
+	///
+	/// The pointer starts moving right.
+	pub fn set_direction(&mut self, direction: Direction) {
+		self.direction = direction;
+	}
+
+	/// Treat the character as an extra instruction which ends the program.
+	///
+	/// This takes precedence over any other meaning of the character.
+	/// The `@` instruction always ends the program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, TerminationReason};
+	///
+	/// let program = Program::new(vec!["1.Q2.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.add_end_instruction('Q');
+	///
+	/// let result = interpreter.execute_with_limit(1000);
+	/// assert_eq!(result.terminated, TerminationReason::Halted);
+	/// assert_eq!(result.output, "1 ");
+	/// ```
+	pub fn add_end_instruction(&mut self, c: char) {
+		self.end_instructions.insert(c);
+	}
+
+	/// Set how output is formatted.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, OutputFormat, Program};
+	///
+	/// // Writes 2048 * 27 = 0xD800, a surrogate which is not a valid character.
+	/// let program = Program::new(vec!["88*8*4*93**,@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_output_format(OutputFormat { replacement: '?', ..OutputFormat::default() });
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "?");
+	/// ```
+	pub fn set_output_format(&mut self, format: OutputFormat) {
+		self.format = format;
+	}
+
+	/// Translate values written by `,` into other characters.
+	///
+	/// Values which are not in the table are written unchanged, so an empty
+	/// table, the default, writes every value as it is.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	/// use std::collections::HashMap;
+	///
+	/// let program = Program::new(vec!["0,@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let mut table = HashMap::new();
+	/// table.insert(0, 'Z');
+	/// interpreter.set_char_table(table);
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "Z");
+	/// ```
+	pub fn set_char_table(&mut self, table: HashMap<i64, char>) {
+		self.char_table = table;
+	}
+
+	/// Translate newlines written by `,` into the given sequence.
+	///
+	/// For example, `PLATFORM_NEWLINE` writes `\r\n` on Windows.
+	/// By default no translation is done. Pass `None` to disable it again.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["55+,@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_newline(Some("\r\n".to_string()));
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "\r\n");
+	/// ```
+	pub fn set_newline(&mut self, newline: Option<String>) {
+		self.newline = newline;
+	}
+
+	/// Read input for `&` and `~` from the reader instead of standard input.
+	///
+	/// Any user values given to `with_values` are read first. At the end of
+	/// the input both instructions push -1.
+	///
+	/// `&` skips anything before the next number, such as whitespace or
+	/// letters, and reads an optional `-` followed by digits. The character
+	/// after the number is left for the next read. If there is no number in
+	/// the rest of the input `&` pushes -1 and consumes nothing, so a
+	/// following `~` still reads those characters. Only input the reader has
+	/// already buffered, such as the current line of a terminal, is searched.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["&~&.~.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_input(&b"  12x-3"[..]);
+	///
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["-3 -1 "]);
+	/// assert_eq!(interpreter.stack_dump(), "120 12");
+	///
+	/// let program = Program::new(vec!["&~@".chars().collect()]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program.clone());
+	/// interpreter.set_input(&b"42 "[..]);
+	/// interpreter.execute().unwrap();
+	/// assert_eq!(interpreter.stack().as_slice(), &[42, 32]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_input(&b"-7\n"[..]);
+	/// interpreter.execute().unwrap();
+	/// assert_eq!(interpreter.stack().as_slice(), &[-7, 10]);
+	///
+	/// // The `x` is skipped rather than read as 0.
+	/// let program = Program::new(vec!["&&@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_input(&b"x5"[..]);
+	/// interpreter.execute().unwrap();
+	/// assert_eq!(interpreter.stack().as_slice(), &[5, -1]);
+	///
+	/// // Without a number the `x` is left for the `~`.
+	/// let program = Program::new(vec!["&~@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_input(&b"x"[..]);
+	/// interpreter.execute().unwrap();
+	/// assert_eq!(interpreter.stack().as_slice(), &[-1, 'x' as i64]);
+	/// ```
+	pub fn set_input<R: BufRead + 'static>(&mut self, input: R) {
+		self.input = Box::new(input);
+	}
+
+	/// Whether `&` or `~` has reached the end of the input and pushed -1.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["~~~@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_input(&b"hi"[..]);
+	///
+	/// interpreter.step();
+	/// interpreter.step();
+	/// assert!(!interpreter.input_exhausted());
+	///
+	/// interpreter.execute().unwrap();
+	/// assert!(interpreter.input_exhausted());
+	/// assert_eq!(interpreter.stack().as_slice(), &['h' as i64, 'i' as i64, -1]);
+	/// ```
+	pub fn input_exhausted(&self) -> bool {
+		self.input_exhausted
+	}
+
+	/// Write each character read by `~` to the output.
+	///
+	/// This is useful when running interactively. It is off by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["~~@".chars().collect()]);
+	/// let mut interpreter = Interpreter::with_values(program, vec!['h' as i64, 'i' as i64]);
+	/// interpreter.set_echo_input(true);
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "hi");
+	/// ```
+	pub fn set_echo_input(&mut self, echo_input: bool) {
+		self.echo_input = echo_input;
+	}
+
+	/// Send output over a channel instead of writing it.
+	///
+	/// Each `.` and `,` sends an `OutputEvent` as soon as it runs, so another
+	/// thread can display output while the program executes. Pass `None` to
+	/// write output again.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, OutputEvent, Program};
+	/// use std::sync::mpsc::channel;
+	/// use std::thread;
+	///
+	/// let (sender, receiver) = channel();
+	///
+	/// let worker = thread::spawn(move || {
+	///     let program = Program::new(vec!["\"!\"7.,@".chars().collect()]);
+	///     let mut interpreter = Interpreter::from_program(program);
+	///     interpreter.set_output_channel(Some(sender));
+	///     interpreter.execute().unwrap();
+	/// });
+	///
+	/// let events: Vec<OutputEvent> = receiver.iter().collect();
+	/// worker.join().unwrap();
+	///
+	/// assert_eq!(events, vec![OutputEvent::Integer(7), OutputEvent::Char('!')]);
+	/// ```
+	pub fn set_output_channel(&mut self, sender: Option<Sender<OutputEvent>>) {
+		self.output_channel = sender;
+	}
+
+	/// Call the hook before running each instruction, allowing it to be replaced.
+	///
+	/// The hook receives the `[row, col]` position and the instruction. If
+	/// it returns a character, that is run instead, but the program itself is
+	/// left unchanged. Characters read in string mode are not passed to it.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["52+.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.on_instruction(|_, c| if c == '+' { Some('-') } else { None });
+	///
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["3 "]);
+	/// assert_eq!(interpreter.program().get(2, 0), '+');
+	/// ```
+	pub fn on_instruction<F: FnMut([usize; 2], char) -> Option<char> + 'static>(&mut self, hook: F) {
+		self.instruction_hook = Some(Box::new(hook));
+	}
+
+	/// Call the hook after each `+`, `-`, `*`, `/` and `%` with its operands and result.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{ArithmeticEvent, Interpreter, Program};
+	/// use std::cell::RefCell;
+	/// use std::rc::Rc;
+	///
+	/// let program = Program::new(vec!["23+.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let events = Rc::new(RefCell::new(Vec::new()));
+	/// let recorded = events.clone();
+	/// interpreter.on_arithmetic(move |event| recorded.borrow_mut().push(event));
+	/// interpreter.execute_capture_lines();
+	///
+	/// assert_eq!(*events.borrow(), vec![ArithmeticEvent { op: '+', left: 2, right: 3, result: 5 }]);
+	/// ```
+	pub fn on_arithmetic<F: FnMut(ArithmeticEvent) + 'static>(&mut self, hook: F) {
+		self.arithmetic_hook = Some(Box::new(hook));
+	}
+
+	/// Call the hook after each instruction is processed, including
+	/// characters read in string mode.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Interpreter, Program, TraceEvent};
+	/// use std::cell::RefCell;
+	/// use std::rc::Rc;
+	///
+	/// let program = Program::new(vec!["1v".chars().collect(), " @".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let events = Rc::new(RefCell::new(Vec::new()));
+	/// let recorded = events.clone();
+	/// interpreter.on_trace(move |event| recorded.borrow_mut().push(event));
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(*events.borrow(), vec![
+	///     TraceEvent { pos: [0, 0], instruction: '1', direction: Direction::Right, stack_len: 1 },
+	///     TraceEvent { pos: [0, 1], instruction: 'v', direction: Direction::Right, stack_len: 1 },
+	///     TraceEvent { pos: [1, 1], instruction: '@', direction: Direction::Down, stack_len: 1 },
+	/// ]);
+	/// ```
+	pub fn on_trace<F: FnMut(TraceEvent) + 'static>(&mut self, hook: F) {
+		self.trace_hook = Some(Box::new(hook));
+	}
+
+	/// Call the hook whenever `/` or `%` divides by zero.
+	///
+	/// The hook is called in addition to the usual result of zero being pushed.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{DivisionByZero, Interpreter, Program};
+	/// use std::cell::RefCell;
+	/// use std::rc::Rc;
+	///
+	/// let program = Program::new(vec!["50/.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let events = Rc::new(RefCell::new(Vec::new()));
+	/// let recorded = events.clone();
+	/// interpreter.on_division_by_zero(move |event| recorded.borrow_mut().push(event));
+	///
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["0 "]);
+	/// assert_eq!(*events.borrow(), vec![DivisionByZero { op: '/', dividend: 5, pos: [0, 2] }]);
+	/// ```
+	pub fn on_division_by_zero<F: FnMut(DivisionByZero) + 'static>(&mut self, hook: F) {
+		self.division_hook = Some(Box::new(hook));
+	}
+
+	/// Set how `stack_dump` and `render_full` show stack values.
+	///
+	/// Values are shown in decimal by default.
+	pub fn set_stack_format(&mut self, format: StackFormat) {
+		self.stack_format = format;
+	}
+
+	/// The stack, top first, with values separated by spaces.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, StackFormat};
+	///
+	/// let program = Program::new(vec!["1\"A\"@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.execute_capture_lines();
+	///
+	/// assert_eq!(interpreter.stack_dump(), "65 1");
+	///
+	/// interpreter.set_stack_format(StackFormat::Hex);
+	/// assert_eq!(interpreter.stack_dump(), "41 1");
+	///
+	/// interpreter.set_stack_format(StackFormat::Char);
+	/// assert_eq!(interpreter.stack_dump(), "A \u{1}");
+	/// ```
+	pub fn stack_dump(&self) -> String {
+		self.stack.stack.iter()
+			.rev()
+			.map(|value| self.stack_format.value(*value))
+			.collect::<Vec<String>>()
+			.join(" ")
+	}
+
+	/// Use the clock to measure timeouts instead of the system clock.
+	pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+		self.clock = Box::new(clock);
+	}
+
+	/// Call the hook whenever the pointer changes direction.
+	///
+	/// The hook receives the old and new directions. Instructions which leave
+	/// the direction as it was, such as `>` while already moving right, do not
+	/// call it.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Interpreter, Program};
+	/// use std::cell::RefCell;
+	/// use std::rc::Rc;
+	///
+	/// let program = Program::new(vec![
+	///     ">v".chars().collect(),
+	///     "@<".chars().collect(),
+	/// ]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let changes = Rc::new(RefCell::new(Vec::new()));
+	/// let recorded = changes.clone();
+	/// interpreter.on_direction_change(move |old, new| recorded.borrow_mut().push((old, new)));
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(*changes.borrow(), vec![
+	///     (Direction::Right, Direction::Down),
+	///     (Direction::Down, Direction::Left),
+	/// ]);
+	/// ```
+	pub fn on_direction_change<F: FnMut(Direction, Direction) + 'static>(&mut self, hook: F) {
+		self.direction_hook = Some(Box::new(hook));
+	}
+
+	/// End the program as soon as it writes any output.
+	///
+	/// The output is still written as usual and is also available from
+	/// `last_output`. It is off by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, OutputEvent, Program, TerminationReason};
+	///
+	/// let program = Program::new(vec!["123...@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_halt_on_first_output(true);
+	///
+	/// let result = interpreter.execute_with_limit(1000);
+	/// assert_eq!(result.terminated, TerminationReason::Halted);
+	/// assert_eq!(result.output, "3 ");
+	/// assert_eq!(interpreter.last_output(), Some(OutputEvent::Integer(3)));
+	/// ```
+	pub fn set_halt_on_first_output(&mut self, halt_on_first_output: bool) {
+		self.halt_on_first_output = halt_on_first_output;
+	}
+
+	/// The most recent output written by the program.
+	pub fn last_output(&self) -> Option<OutputEvent> {
+		self.last_output
+	}
+
+	/// Warn when `p` writes the same cell `threshold` times in a row.
+	///
+	/// Writes to any other cell reset the count. This is only a heuristic,
+	/// a program may legitimately rewrite one cell many times, so it is
+	/// disabled by default. Pass `None` to disable it again.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, Warning};
+	///
+	/// // Writes to the cell at row 1, column 0 forever, four steps a write.
+	/// let program = Program::new(vec!["101p".chars().collect(), " ".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_p_loop_threshold(Some(100));
+	///
+	/// interpreter.execute_with_limit(399);
+	/// assert!(interpreter.warnings().is_empty());
+	///
+	/// interpreter.execute_with_limit(1);
+	///
+	/// assert_eq!(interpreter.warnings()[0],
+	///            Warning::SelfModifyLoop { pos: [1, 0], writes: 100 });
+	/// ```
+	pub fn set_p_loop_threshold(&mut self, threshold: Option<usize>) {
+		self.p_loop_threshold = threshold;
+	}
+
+	/// The program being run, including any changes made by `p`.
+	pub fn program(&self) -> &Program {
+		&self.program
+	}
+
+	/// The `[row, col]` position of the instruction pointer.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["1v".chars().collect(), " 2".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.step();
+	/// interpreter.step();
+	///
+	/// assert_eq!(interpreter.position(), [1, 1]);
+	/// assert_eq!(interpreter.direction(), Direction::Down);
+	/// assert_eq!(interpreter.stack().as_slice(), &[1]);
+	/// ```
+	pub fn position(&self) -> [usize; 2] {
+		self.pos
+	}
+
+	/// The direction the instruction pointer is moving in.
+	pub fn direction(&self) -> Direction {
+		self.direction
+	}
+
+	/// The stack of the running program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["0123456789@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.stack().as_slice(), &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+	/// ```
+	///
+	/// Only `0` to `9` push their value; `a` is not an instruction and `:`
+	/// duplicates the top of the stack.
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["7a:@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.stack().as_slice(), &[7, 7]);
+	/// ```
+	pub fn stack(&self) -> &Stack {
+		&self.stack
+	}
+
+	/// Make `p` do nothing, so the program cannot change itself.
+	///
+	/// `p` still pops its three values, and `g` reads the program as usual.
+	/// It is off by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Writes a 2 over the 1 before running it.
+	/// let program = Program::from_str("\"2\"60p1.@");
+	///
+	/// let mut interpreter = Interpreter::from_program(program.clone());
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["2 "]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_frozen_grid(true);
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["1 "]);
+	/// ```
+	pub fn set_frozen_grid(&mut self, frozen: bool) {
+		self.frozen_grid = frozen;
+	}
+
+	/// Stop the program with `BefungeError::StackUnderflow` when an
+	/// instruction would pop more values than are on the stack.
+	///
+	/// The instruction is not run. By default popping an empty stack gives 0.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program};
+	///
+	/// let program = Program::from_str("1+.@");
+	///
+	/// let mut interpreter = Interpreter::from_program(program.clone());
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["1 "]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_underflow_is_error(true);
+	/// assert_eq!(interpreter.execute(), Err(BefungeError::StackUnderflow { pos: [0, 1] }));
+	/// assert_eq!(interpreter.stack().as_slice(), &[1]);
+	///
+	/// // The `+` did not run, so it is not counted.
+	/// assert_eq!(interpreter.metrics().steps, 1);
+	/// assert!(interpreter.coverage().unexecuted.contains(&'+'));
+	/// ```
+	pub fn set_underflow_is_error(&mut self, underflow_is_error: bool) {
+		self.underflow_is_error = underflow_is_error;
+	}
+
+	/// Only let `g` and `p` use cells inside the Befunge-93 playfield.
+	///
+	/// In strict mode coordinates outside of the `PLAYFIELD_WIDTH` by
+	/// `PLAYFIELD_HEIGHT` field are out of range even if the program is
+	/// larger: `g` pushes 0 and `p` does nothing, as for coordinates outside
+	/// of the program. It is off by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Get the `A` at x = 85, past the edge of the playfield.
+	/// let line = format!("99*4+0g.@{}A", " ".repeat(76));
+	/// let program = Program::new(vec![line.chars().collect()]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program.clone());
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["65 "]);
+	///
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_strict(true);
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["0 "]);
+	/// ```
+	pub fn set_strict(&mut self, strict: bool) {
+		self.strict = strict;
+	}
+
+	/// Stop the program if it tries to make more than this many `p` writes.
+	///
+	/// The write which goes over the limit is not made, and `execute`
+	/// returns `BefungeError::SelfModifyLimitExceeded`. Writes outside of the
+	/// program are ignored so do not count. There is no limit by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program};
+	///
+	/// // Writes to the same cell forever.
+	/// let program = Program::new(vec![">11p v".chars().collect(), "^    <".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_max_self_modifications(Some(3));
+	///
+	/// assert_eq!(interpreter.execute(),
+	///            Err(BefungeError::SelfModifyLimitExceeded { limit: 3 }));
+	/// ```
+	pub fn set_max_self_modifications(&mut self, limit: Option<usize>) {
+		self.max_self_modifications = limit;
+	}
+
+	/// Stop the program if it tries to write more than this many newlines.
+	///
+	/// The newline which goes over the limit is not written, and
+	/// `execute` returns `BefungeError::NewlineLimitExceeded`. The
+	/// newlines written are counted in `Metrics::newlines`. There is no limit
+	/// by default.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["55+:,:,,@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_max_newlines(Some(2));
+	///
+	/// assert_eq!(interpreter.execute(), Err(BefungeError::NewlineLimitExceeded { limit: 2 }));
+	/// assert_eq!(interpreter.metrics().newlines, 2);
+	/// ```
+	pub fn set_max_newlines(&mut self, limit: Option<usize>) {
+		self.max_newlines = limit;
+	}
+
+	/// The most recent cell written by `p`.
+	///
+	/// Gives the `[row, col]` position of the cell along with its
+	/// old and new characters.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Writes 'a' to (0, 1) and then 'b' to (1, 1).
+	/// let program = Program::new(vec!["\"a\"01p\"b\"11p@".chars().collect(),
+	///                                 "xy".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.last_modification(), Some(([1, 1], 'y', 'b')));
+	/// ```
+	pub fn last_modification(&self) -> Option<([usize; 2], char, char)> {
+		self.last_modification
+	}
+
+	/// Change a cell of the running program from outside of it.
+	///
+	/// The change is recorded in `patches` rather than being treated as a
+	/// `p` write.
+	///
+	/// Returns `BefungeError::OutOfBounds` if the position is outside of the
+	/// program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["1.  @".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.run_until_stack(|stack| stack == [1], 10);
+	/// interpreter.patch_cell([0, 2], '7').unwrap();
+	/// interpreter.patch_cell([0, 3], '.').unwrap();
+	/// assert_eq!(interpreter.patch_cell([1, 0], '.'), Err(BefungeError::OutOfBounds { pos: [1, 0] }));
+	///
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "1 7 ");
+	/// assert_eq!(interpreter.last_modification(), None);
+	/// ```
+	pub fn patch_cell(&mut self, pos: [usize; 2], c: char) -> Result<(), BefungeError> {
+		if !self.program.contains(pos[1], pos[0]) {
+			return Err(BefungeError::OutOfBounds { pos });
+		}
+
+		let old = self.program.get_instruction_char(pos);
+		self.program.set_instruction_char(pos, c);
+		self.patches.push((pos, old, c));
+		Ok(())
+	}
+
+	/// Cells changed by `patch_cell`, oldest first.
+	///
+	/// Gives the `[row, col]` position of each cell along with its
+	/// old and new characters.
+	pub fn patches(&self) -> &[([usize; 2], char, char)] {
+		&self.patches
+	}
+
+	/// Record a copy of the stack after each instruction.
+	///
+	/// Only the most recent `limit` copies are kept. Recording is off by
+	/// default, pass `None` to turn it off again.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["12+@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.record_stack_history(Some(3));
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.stack_history(), &[vec![1, 2], vec![3], vec![3]]);
+	/// ```
+	pub fn record_stack_history(&mut self, limit: Option<usize>) {
+		self.stack_history_limit = limit;
+	}
+
+	/// The recorded copies of the stack, oldest first.
+	pub fn stack_history(&self) -> &VecDeque<Vec<i64>> {
+		&self.stack_history
+	}
+
+	/// Warnings raised while running the program.
+	pub fn warnings(&self) -> &[Warning] {
+		&self.warnings
+	}
+
+	/// Gets the next user value from the front of the queue.
+	///
+	/// Returns `None` if there are no user values remaining.
+	///
+	/// See `queue_input` for adding more values.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["@".chars().collect()]);
+	/// let mut interpreter = Interpreter::with_values(program, vec![1, 2, 3]);
+	///
+	/// assert_eq!(interpreter.next_value(), Some(1));
+	/// assert_eq!(interpreter.next_value(), Some(2));
+	/// assert_eq!(interpreter.next_value(), Some(3));
+	/// assert_eq!(interpreter.next_value(), None);
+	/// ```
+	pub fn next_value(&mut self) -> Option<i64> {
+		self.values.pop_front()
+	}
+
+	/// Add user values to the back of the queue read by `&` and `~`.
+	///
+	/// Values are read first in, first out, after any values already
+	/// queued and before reading from the input.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["&&&...@".chars().collect()]);
+	/// let mut interpreter = Interpreter::with_values(program, vec![1]);
+	/// interpreter.queue_input(&[2, 3]);
+	///
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["3 2 1 "]);
+	/// ```
+	pub fn queue_input(&mut self, values: &[i64]) {
+		self.values.extend(values);
+	}
+
+	/// Measurements taken while running the program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["&~@".chars().collect()]);
+	/// let mut interpreter = Interpreter::with_values(program, vec![1, 2, 3]);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.metrics().integers_read, 1);
+	/// assert_eq!(interpreter.metrics().chars_read, 1);
+	///
+	/// // Four writes to three distinct cells.
+	/// let program = Program::new(vec!["000p010p020p000p@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.metrics().cells_modified, 3);
+	/// ```
+	pub fn metrics(&self) -> &Metrics {
+		&self.metrics
+	}
+
+	/// Number of times each character was executed as an instruction.
+	///
+	/// Characters pushed in string mode are data, so they are not counted,
+	/// although the `"` which closes the string is.
+	pub fn instruction_histogram(&self) -> &HashMap<char, usize> {
+		&self.histogram
+	}
+
+	/// Reports which instructions in the program were executed.
+	///
+	/// The instructions are taken from the current state of the program,
+	/// so cells rewritten by `p` are reported as they are now.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // The `_` always sends the pointer left, so `3.` is never run.
+	/// let instructions = vec!["1v".chars().collect(), "@_3.".chars().collect()];
+	/// let program = Program::new(instructions);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// let coverage = interpreter.coverage();
+	/// assert!(coverage.executed.contains(&'_'));
+	/// assert!(coverage.unexecuted.contains(&'3'));
+	/// assert!(coverage.unexecuted.contains(&'.'));
+	/// ```
+	///
+	/// Characters inside a string are pushed rather than executed.
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["\"g\"$@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// let coverage = interpreter.coverage();
+	/// assert!(coverage.unexecuted.contains(&'g'));
+	/// assert!(coverage.executed.contains(&'$'));
+	/// ```
+	pub fn coverage(&self) -> Coverage {
+		let (executed, unexecuted) = self.program.instruction_set()
+			.into_iter()
+			.partition(|c| self.histogram.contains_key(c));
+
+		Coverage {
+			executed,
+			unexecuted,
+		}
+	}
+
+	/// The program source annotated with how often each cell was executed.
+	///
+	/// Each line of the program is followed by a line of marks. Cells which
+	/// were never executed are left blank, otherwise the mark is the number
+	/// of digits in the execution count: `1` for 1 to 9 times, `2` for 10 to
+	/// 99 times and so on, with `+` for counts of ten digits or more. Tab
+	/// cells are shown as `TAB_SYMBOL` so the marks line up.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["1#2@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.execute().unwrap();
+	///
+	/// assert_eq!(interpreter.annotated_source(), "1#2@\n11 1\n");
+	/// ```
+	pub fn annotated_source(&self) -> String {
+		let mut annotated = String::new();
+
+		for row in 0..self.program.lines() {
+			let line: String = (0..self.program.chars_in_line(row))
+				.map(|col| visible_char(self.program.get_instruction_char([row, col])))
+				.collect();
+			let marks: String = (0..self.program.chars_in_line(row))
+				.map(|col| match self.cell_counts[row][col] {
+					0 => ' ',
+					count => char::from_digit(count.to_string().len() as u32, 10).unwrap_or('+'),
+				})
+				.collect();
+
+			annotated.push_str(&line);
+			annotated.push('\n');
+			annotated.push_str(marks.trim_end());
+			annotated.push('\n');
+		}
+
+		annotated
+	}
+
+	/// Render the program and the state of the interpreter.
+	///
+	/// The line below the pointer's row marks its column with `^`. To the
+	/// right of the program is the direction, the number of steps taken and
+	/// the stack, top first. Tab cells are shown as `TAB_SYMBOL` to keep the
+	/// grid aligned, although `g` still reads them as 9.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["12.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// interpreter.run_until_stack(|stack| stack.len() == 2, 10);
+	///
+	/// let rendered = interpreter.render_full();
+	/// let lines: Vec<&str> = rendered.lines().collect();
+	///
+	/// assert_eq!(lines, ["12.@ | direction: Right",
+	///                    "  ^  | steps: 2",
+	///                    "     | stack:",
+	///                    "     | 2",
+	///                    "     | 1"]);
+	///
+	/// let program = Program::new(vec!["1\t2@".chars().collect(), "3456".chars().collect()]);
+	/// let interpreter = Interpreter::from_program(program);
+	/// let rendered = interpreter.render_full();
+	/// let lines: Vec<&str> = rendered.lines().collect();
+	///
+	/// assert_eq!(lines, ["1→2@ | direction: Right",
+	///                    "^    | steps: 0",
+	///                    "3456 | stack:"]);
+	/// ```
+	pub fn render_full(&self) -> String {
+		let width = (0..self.program.lines())
+			.map(|row| self.program.chars_in_line(row))
+			.max()
+			.unwrap_or(0);
+
+		let mut grid: Vec<String> = (0..self.program.lines())
+			.map(|row| {
+				let line: String = (0..self.program.chars_in_line(row))
+					.map(|col| visible_char(self.program.get_instruction_char([row, col])))
+					.collect();
+				format!("{:width$}", line, width = width)
+			})
+			.collect();
+		if self.pos[0] < grid.len() {
+			let marker = format!("{:>col$}", "^", col = self.pos[1] + 1);
+			grid.insert(self.pos[0] + 1, format!("{:width$}", marker, width = width));
+		}
+
+		let mut sidebar = vec![
+			format!("direction: {:?}", self.direction),
+			format!("steps: {}", self.metrics.steps),
+			"stack:".to_string(),
+		];
+		sidebar.extend(self.stack.stack.iter().rev().map(|value| self.stack_format.value(*value)));
+
+		let blank = " ".repeat(width);
+		let mut rendered = String::new();
+		for i in 0..grid.len().max(sidebar.len()) {
+			let left = grid.get(i).unwrap_or(&blank);
+			let right = sidebar.get(i).map_or("", |s| s.as_str());
+			rendered.push_str(format!("{} | {}", left, right).trim_end());
+			rendered.push('\n');
+		}
+
+		rendered
+	}
+
+	/// Reset the interpreter so the program can be run again from the start.
+	///
+	/// This restores the original instructions, undoing changes made by `p`
+	/// and `patch_cell`, empties the stack, moves the pointer back to the top
+	/// left moving right and clears the metrics, history and any error.
+	/// Settings, hooks and the input are kept, and user values which have
+	/// already been read are not restored.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// // Overwrites its own `1` with a `2` after printing it.
+	/// let program = Program::new(vec!["1.\"2\"00p@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let first = interpreter.execute_capture_lines();
+	/// assert_eq!(interpreter.program().get(0, 0), '2');
+	///
+	/// interpreter.reset();
+	/// assert_eq!(interpreter.program().get(0, 0), '1');
+	/// assert_eq!(interpreter.execute_capture_lines(), first);
+	/// assert_eq!(interpreter.metrics().steps, 9);
+	/// ```
+	pub fn reset(&mut self) {
+		self.program.instructions = self.program.original.clone();
+		self.stack = Stack::default();
+		self.direction = Direction::Right;
+		self.state = State::Normal;
+		self.pos = [0, 0];
+		self.metrics = Metrics::default();
+		self.histogram.clear();
+		for row in &mut self.cell_counts {
+			row.iter_mut().for_each(|count| *count = 0);
+		}
+		self.last_output = None;
+		self.last_write = None;
+		self.last_modification = None;
+		self.patches.clear();
+		self.stack_history.clear();
+		self.warnings.clear();
+		self.self_modifications = 0;
+		self.modified_cells.clear();
+		self.error = None;
+		self.input_exhausted = false;
+	}
+
+	/// Execute the program with the interpreter.
+	///
+	/// Returns an error if the program was stopped because it broke one of
+	/// the interpreter's limits or its output could not be written.
+	///
+	/// Only the program's own output is written, and it is flushed after
+	/// each `.` and `,`.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	/// use std::cell::RefCell;
+	/// use std::io::{self, Write};
+	/// use std::rc::Rc;
+	///
+	/// // Records each write and flush.
+	/// #[derive(Clone, Default)]
+	/// struct Recorder(Rc<RefCell<Vec<String>>>);
+	///
+	/// impl Write for Recorder {
+	///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+	///         self.0.borrow_mut().push(String::from_utf8_lossy(buf).into_owned());
+	///         Ok(buf.len())
+	///     }
+	///
+	///     fn flush(&mut self) -> io::Result<()> {
+	///         self.0.borrow_mut().push("flush".to_string());
+	///         Ok(())
+	///     }
+	/// }
+	///
+	/// let recorder = Recorder::default();
+	/// let mut interpreter = Interpreter::with_output(Program::from_str("1.\"a\",@"), recorder.clone());
+	///
+	/// assert_eq!(interpreter.execute(), Ok(()));
+	/// assert_eq!(*recorder.0.borrow(), ["1 ", "flush", "a", "flush"]);
+	/// ```
+	pub fn execute(&mut self) -> Result<(), BefungeError> {
+		while self.step() == StepOutcome::Running {}
+
+		match self.error {
+			Some(error) => Err(error),
+			None => Ok(()),
+		}
+	}
+
+	/// Execute the program to the end, writing a CSV row for each step.
+	///
+	/// The first row is the header `row,col,instr,direction,stack_len`. Each
+	/// following row gives the pointer's position and direction, the
+	/// instruction and the stack size just before the instruction runs.
+	/// Instructions which need quoting in CSV, such as `,`, are quoted.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["12+$@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let mut csv = Vec::new();
+	/// interpreter.execute_trace_csv(&mut csv).unwrap();
+	/// let csv = String::from_utf8(csv).unwrap();
+	/// let rows: Vec<&str> = csv.lines().collect();
+	///
+	/// assert_eq!(rows, ["row,col,instr,direction,stack_len",
+	///                   "0,0,1,Right,0",
+	///                   "0,1,2,Right,1",
+	///                   "0,2,+,Right,2",
+	///                   "0,3,$,Right,1",
+	///                   "0,4,@,Right,0"]);
+	/// ```
+	pub fn execute_trace_csv<W: Write>(&mut self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "row,col,instr,direction,stack_len")?;
+
+		loop {
+			let c = self.program.cell(self.pos);
+			let instr = match c {
+				',' | '\n' | '\r' => format!("\"{}\"", c),
+				'"' => "\"\"\"\"".to_string(),
+				_ => c.to_string(),
+			};
+			writeln!(writer, "{},{},{},{:?},{}",
+				self.pos[0], self.pos[1], instr, self.direction, self.stack.stack.len())?;
+
+			if self.step() == StepOutcome::Ended {
+				return Ok(());
+			}
+		}
+	}
+
+	/// Execute the program, capturing its output, for at most `max_steps` instructions.
+	///
+	/// Every instruction processed counts as a step, so `#` is one step and
+	/// the cell it jumps over is not counted. The result says whether the
+	/// program ended, was stopped by the limit or by an error, and how many
+	/// steps it took.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{BefungeError, Interpreter, Program, TerminationReason};
+	///
+	/// // Loops forever.
+	/// let program = Program::from_str(">1.v\n^  <");
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let result = interpreter.execute_with_limit(100);
+	/// assert_eq!(result.terminated, TerminationReason::StepLimit);
+	/// assert_eq!(result.steps, 100);
+	///
+	/// let program = Program::from_str("1#2.@");
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let result = interpreter.execute_with_limit(100);
+	/// assert_eq!(result.terminated, TerminationReason::Halted);
+	/// assert_eq!(result.steps, 4);
+	/// assert_eq!(result.output, "1 ");
+	///
+	/// let program = Program::from_str("55+,55+,@");
+	/// let mut interpreter = Interpreter::from_program(program);
+	/// interpreter.set_max_newlines(Some(1));
+	///
+	/// let result = interpreter.execute_with_limit(100);
+	/// assert_eq!(result.terminated,
+	///            TerminationReason::Error(BefungeError::NewlineLimitExceeded { limit: 1 }));
+	/// ```
+	pub fn execute_with_limit(&mut self, max_steps: usize) -> ExecutionResult {
+		let start = self.metrics.steps;
+		let (output, terminated) = self.with_captured_output(|interpreter| {
+			while interpreter.metrics.steps - start < max_steps {
+				if interpreter.step() == StepOutcome::Ended {
+					return match interpreter.error {
+						Some(error) => TerminationReason::Error(error),
+						None => TerminationReason::Halted,
+					};
+				}
+			}
+
+			TerminationReason::StepLimit
+		});
+
+		ExecutionResult {
+			output,
+			steps: self.metrics.steps - start,
+			terminated,
+		}
+	}
+
+	/// Execute the program, capturing its output, until it ends or the
+	/// duration has elapsed.
 	///
-	/// If the stack does not contain two items then zeros
-	/// are pushed to the stack.
-	pub fn switch_top(&mut self) {
-		let a = self.stack.pop();
-
-		if let Some(a) = a {
-			let b = self.stack.pop();
+	/// To keep the cost of reading the clock low it is only checked every
+	/// thousand instructions, so the program may run a little past the
+	/// deadline.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, TerminationReason};
+	/// use std::time::Duration;
+	///
+	/// // Bounces between the arrows forever.
+	/// let program = Program::from_str("><");
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let result = interpreter.execute_with_timeout(Duration::from_millis(1));
+	/// assert_eq!(result.terminated, TerminationReason::Timeout);
+	/// assert!(result.steps.is_multiple_of(1000));
+	///
+	/// let program = Program::from_str("1.@");
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let result = interpreter.execute_with_timeout(Duration::from_secs(1));
+	/// assert_eq!(result.terminated, TerminationReason::Halted);
+	/// assert_eq!(result.output, "1 ");
+	/// ```
+	pub fn execute_with_timeout(&mut self, dur: Duration) -> ExecutionResult {
+		let deadline = self.clock.elapsed() + dur;
+		let start = self.metrics.steps;
+		let (output, terminated) = self.with_captured_output(|interpreter| loop {
+			if interpreter.step() == StepOutcome::Ended {
+				return match interpreter.error {
+					Some(error) => TerminationReason::Error(error),
+					None => TerminationReason::Halted,
+				};
+			}
 
-			if let Some(b) = b {
-				// Push in reverse order.
-				self.stack.push(a);
-				self.stack.push(b);
-			} else {
-				// Push a back and a zero.
-				self.stack.push(a);
-				self.stack.push(0);
+			let steps = interpreter.metrics.steps - start;
+			if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && interpreter.clock.elapsed() >= deadline {
+				return TerminationReason::Timeout;
 			}
-			
-		} else {
-			// Fill stack with two zeros.
-			self.stack.push(0);
-			self.stack.push(0);
+		});
+
+		ExecutionResult {
+			output,
+			steps: self.metrics.steps - start,
+			terminated,
 		}
 	}
-}
 
-/// The Befunge program
-///
-/// Contains the user specified values and the
-/// instructions.
-pub struct Program {
-	values: Vec<u32>,
-	instructions: Vec<Vec<char>>,
-}
+	/// Execute the program to the end and return its output split into lines.
+	///
+	/// A newline at the very end of the output does not start another line,
+	/// and a `\r` before a newline is dropped.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	///
+	/// let program = Program::new(vec!["1.55+,2.55+,@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// assert_eq!(interpreter.execute_capture_lines(), vec!["1 ", "2 "]);
+	/// ```
+	pub fn execute_capture_lines(&mut self) -> Vec<String> {
+		let (output, ()) = self.with_captured_output(|interpreter| {
+			while interpreter.step() == StepOutcome::Running {}
+		});
 
-impl Program {
-	/// Create a new Befunge program with specified user values
-	/// and instructions.
-	pub fn new(values: Vec<u32>, instructions: Vec<Vec<char>>) -> Program {
-		Program {
-			values: values,
-			instructions: instructions,
-		}
+		output.lines().map(|line| line.to_string()).collect()
 	}
 
-	/// Number of lines in the Befunge program.
-	pub fn lines(&self) -> usize {
-		self.instructions.len()
+	/// Execute the program, capturing its output, until it ends or the
+	/// duration has elapsed.
+	///
+	/// Returns the output produced and whether the program finished
+	/// before the deadline. The deadline is checked as by
+	/// `execute_with_timeout`, every thousand instructions.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program};
+	/// use std::time::Duration;
+	///
+	/// // Prints 1 forever.
+	/// let program = Program::new(vec!["1.".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let (output, finished) = interpreter.execute_capture_timeout(Duration::from_millis(10));
+	///
+	/// assert!(!finished);
+	/// assert!(output.starts_with("1 1 "));
+	/// ```
+	pub fn execute_capture_timeout(&mut self, dur: Duration) -> (String, bool) {
+		let result = self.execute_with_timeout(dur);
+
+		(result.output, result.terminated != TerminationReason::Timeout)
 	}
 
-	/// Number of characters in the specified line.
-	pub fn chars_in_line(&self, line: usize) -> usize {
-		self.instructions[line].len()
+	/// Run `f` with the program's output collected into a string, then
+	/// restore the previous output.
+	fn with_captured_output<T, F: FnOnce(&mut Interpreter) -> T>(&mut self, f: F) -> (String, T) {
+		let buffer = Rc::new(RefCell::new(Vec::new()));
+		let output = mem::replace(&mut self.output, Box::new(SharedBuffer(buffer.clone())));
+
+		let result = f(self);
+
+		self.output = output;
+		let captured = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+
+		(captured, result)
 	}
 
-	/// Gets the next user values from the front of the `Vec`.
-	pub fn next_value(&mut self) -> u32 {
-		let value = self.values[0].clone();
-		self.values = self.values.split_off(1);
+	/// Execute the program until the predicate holds for the stack, or at
+	/// most `max_steps` instructions have run.
+	///
+	/// The predicate is given the stack with the top item last and is
+	/// checked before each instruction. Returns `None` if the predicate was
+	/// satisfied, otherwise why the program stopped first.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, TerminationReason};
+	///
+	/// let program = Program::new(vec!["67*.@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// assert_eq!(interpreter.run_until_stack(|stack| stack.last() == Some(&42), 100), None);
+	///
+	/// // Continue from the `.` instruction.
+	/// let output = interpreter.execute_with_limit(1000).output;
+	/// assert_eq!(output, "42 ");
+	///
+	/// // The stack of a loop never changes, so the step limit stops it.
+	/// let program = Program::new(vec![">v".chars().collect(), "^<".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// assert_eq!(interpreter.run_until_stack(|stack| !stack.is_empty(), 100),
+	///            Some(TerminationReason::StepLimit));
+	/// ```
+	pub fn run_until_stack(&mut self, predicate: impl Fn(&[i64]) -> bool,
+						   max_steps: usize) -> Option<TerminationReason> {
+		let start = self.metrics.steps;
+		while !predicate(&self.stack.stack) {
+			if self.metrics.steps - start >= max_steps {
+				return Some(TerminationReason::StepLimit);
+			}
+
+			if self.step() == StepOutcome::Ended {
+				return Some(match self.error {
+					Some(error) => TerminationReason::Error(error),
+					None => TerminationReason::Halted,
+				});
+			}
+		}
 
-		value
+		None
 	}
 
-	/// Gets the instruction character at the given position.
-	pub fn get_instruction_char(&self, pos: [usize;2]) -> char {
-		self.instructions[pos[0]][pos[1]]
+	/// Process the instruction under the pointer and move to the next one.
+	///
+	/// Returns `StepOutcome::Ended` once the program has ended, after which
+	/// the pointer stays on the instruction which ended it. A program with
+	/// no cells ends without running anything.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Interpreter, Program, StepOutcome};
+	///
+	/// let program = Program::new(vec!["12+@".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// assert_eq!(interpreter.step(), StepOutcome::Running);
+	/// assert_eq!(interpreter.step(), StepOutcome::Running);
+	/// assert_eq!(interpreter.stack_dump(), "2 1");
+	///
+	/// assert_eq!(interpreter.step(), StepOutcome::Running);
+	/// assert_eq!(interpreter.stack_dump(), "3");
+	///
+	/// assert_eq!(interpreter.step(), StepOutcome::Ended);
+	/// ```
+	pub fn step(&mut self) -> StepOutcome {
+		self.step_traced().0
 	}
 
-	/// Sets the instruction character at the given position.
-	pub fn set_instruction_char(&mut self, pos: [usize;2], c: char) {
-		self.instructions[pos[0]][pos[1]] = c;
+	/// Iterate over the instructions processed as the program runs.
+	///
+	/// Each item describes one step, as given to the `on_trace` hook. The
+	/// last item is the instruction which ended the program.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use rubefunge_93::befunge::{Direction, Interpreter, Program, TraceEvent};
+	///
+	/// let program = Program::new(vec!["1v".chars().collect(), " @".chars().collect()]);
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// let states: Vec<TraceEvent> = interpreter.states().collect();
+	///
+	/// assert_eq!(states, vec![
+	///     TraceEvent { pos: [0, 0], instruction: '1', direction: Direction::Right, stack_len: 1 },
+	///     TraceEvent { pos: [0, 1], instruction: 'v', direction: Direction::Right, stack_len: 1 },
+	///     TraceEvent { pos: [1, 1], instruction: '@', direction: Direction::Down, stack_len: 1 },
+	/// ]);
+	///
+	/// // Loops forever.
+	/// let program = Program::from_str("><");
+	/// let mut interpreter = Interpreter::from_program(program);
+	///
+	/// assert_eq!(interpreter.states().take(100).count(), 100);
+	/// ```
+	pub fn states(&mut self) -> States<'_> {
+		States { interpreter: self, ended: false }
 	}
-}
 
-/// Direction for the instruction pointer.
-#[derive(PartialEq, Clone, Copy)]
-pub enum Direction {
-	/// Moving up.
-	Up,
-	/// Moving down.
-	Down,
-	/// Moving left.
-	Left,
-	/// Moving right.
-	Right,
-}
+	/// Run a step, returning the trace of the instruction processed, if any.
+	fn step_traced(&mut self) -> (StepOutcome, Option<TraceEvent>) {
+		// A program without any cells has nothing to run.
+		if !self.program.contains(0, 0) {
+			return (StepOutcome::Ended, None);
+		}
 
-/// Current state of the interpreter.
-#[derive(PartialEq, Clone, Copy)]
-pub enum State {
-	/// Normal mode.
-	Normal,
-	/// String capture mode.
-	String,
-}
+		let fetched = self.program.cell(self.pos);
+		let mut instruct_char = fetched;
+		if self.state == State::Normal {
+			if let Some(ref mut hook) = self.instruction_hook {
+				instruct_char = hook(self.pos, instruct_char).unwrap_or(instruct_char);
+			}
 
-/// Actions to be taken by the interpreter.
-#[derive(PartialEq)]
-pub enum Action {
-	/// Change direction of pointer movement.
-	ChangeDir(Direction),
-	/// Change the state of the interpreter.
-	ChangeState(State),
-	/// Skip the next instruction character.
-	Trampoline,
-	/// Take no action.
-	None,
-	/// End the program.
-	End,
-}
+			if self.underflow_is_error
+				&& !self.end_instructions.contains(&instruct_char)
+				&& self.stack.len() < pops(instruct_char) {
+				self.error = Some(BefungeError::StackUnderflow { pos: self.pos });
+				return (StepOutcome::Ended, None);
+			}
+		}
 
-/// The Befunge interpreter.
-pub struct Interpreter {
-	stack: Stack,
-	direction: Direction,
-	state: State,
-	pos: [usize; 2],
-	program: Program,
-}
+		// Only instructions which actually run are counted.
+		self.metrics.steps += 1;
+		self.record_instruction(fetched);
+		let action = self.process_instruction(instruct_char);
 
-impl Interpreter {
-	/// Create a new interpreter from the given program.
-	pub fn from_program(program: Program) -> Interpreter {
-		Interpreter {
-			stack: Stack::default(),
-			direction: Direction::Right,
-			state: State::Normal,
-			pos: [0,0],
-			program: program,
+		let event = TraceEvent {
+			pos: self.pos,
+			instruction: instruct_char,
+			direction: self.direction,
+			stack_len: self.stack.stack.len(),
+		};
+		if let Some(ref mut hook) = self.trace_hook {
+			hook(event);
 		}
-	}
-
-	/// Execute the program with the interpreter.
-	pub fn execute(&mut self) {
-		let instruct_char = self.program.get_instruction_char(self.pos);
-		let mut action = self.process_instruction(instruct_char);
-
-		while action != Action::End {
-			match action {
-				Action::ChangeDir(ref direction) => self.direction = *direction,
-				Action::ChangeState(ref state) => self.state = *state,
-				Action::Trampoline => self.update_pos(),
-				_ => {},
-			} 
 
-			self.update_pos();
+		if let Some(limit) = self.stack_history_limit {
+			self.stack_history.push_back(self.stack.stack.clone());
+			if self.stack_history.len() > limit {
+				self.stack_history.pop_front();
+			}
+		}
 
-			let instruct_char = self.program.get_instruction_char(self.pos);
-			action = self.process_instruction(instruct_char);
+		match action {
+			Action::ChangeDir(direction) => {
+				if direction != self.direction {
+					if let Some(ref mut hook) = self.direction_hook {
+						hook(self.direction, direction);
+					}
+				}
+				self.direction = direction;
+			},
+			Action::ChangeState(state) => self.state = state,
+			Action::Trampoline => self.update_pos(),
+			Action::None => {},
+			Action::End => return (StepOutcome::Ended, Some(event)),
 		}
 
-		self.end_program();
+		self.update_pos();
+		(StepOutcome::Running, Some(event))
+	}
+
+	/// Record the instruction under the pointer in the histogram and the
+	/// cell counts.
+	///
+	/// Characters read in string mode are only added to the histogram if
+	/// they end the string.
+	fn record_instruction(&mut self, instruct_char: char) {
+		if self.state == State::Normal || instruct_char == '"' {
+			*self.histogram.entry(instruct_char).or_insert(0) += 1;
+		}
+		self.cell_counts[self.pos[0]][self.pos[1]] += 1;
 	}
 
 	/// Update the position of the instruction pointer.
 	fn update_pos(&mut self) {
-		match self.direction {
-			Direction::Right => {
-				if self.pos[1] == self.program.chars_in_line(self.pos[0]) - 1 {
-					self.pos[1] = 0;
-				} else {
-					self.pos[1] += 1;
-				}
-			},
-			Direction::Left => {
-				if self.pos[1] == 0 {
-					self.pos[1] = self.program.chars_in_line(self.pos[0]) - 1;
-				} else {
-					self.pos[1] -= 1;
-
-				}
-			},
-			Direction::Up => {
-				if self.pos[0] == 0 {
-					self.pos[0] = self.program.lines() - 1;
-				} else {
-					self.pos[0] -= 1;
-				}
-			},
-			Direction::Down => {
-				if self.pos[0] == self.program.lines() - 1 {
-					self.pos[0] = 0;
-				} else {
-					self.pos[0] += 1;
-				}
-			},
-		}
+		self.pos = self.program.advance(self.pos, self.direction);
 	}
 
 	/// Process the instruction character.
@@ -268,46 +3735,44 @@ impl Interpreter {
 				match instruction {
 					'"' => Action::ChangeState(State::Normal),
 					_ => {
-						self.stack.push(instruction as u32);
+						self.stack.push(i64::from(u32::from(instruction)));
 						Action::None
 						}
 					}
 				},
 
+			State::Normal if self.end_instructions.contains(&instruction) => Action::End,
+
 			State::Normal => {
 				match instruction {
-				'0'...'9' => {
-					self.stack.push(instruction.to_digit(10).unwrap());
-					Action::None
-				},
-				'+' => {
-					let a = self.stack.pop();
-					let b = self.stack.pop();
-					self.stack.push(b + a);
-					Action::None
-				},
-				'-' => {
-					let a = self.stack.pop();
-					let b = self.stack.pop();
-					self.stack.push(b - a);
-					Action::None
-				},
-				'*' => {
-					let a = self.stack.pop();
-					let b = self.stack.pop();
-					self.stack.push(b * a);
-					Action::None
-				},
-				'/' => {
-					let a = self.stack.pop();
-					let b = self.stack.pop();
-					self.stack.push(b / a);
+				'0'..='9' => {
+					// The arm only matches ASCII digits, so no need for `to_digit`.
+					self.stack.push(i64::from(u32::from(instruction) - u32::from('0')));
 					Action::None
 				},
-				'%' => {
+				'+' | '-' | '*' | '/' | '%' => {
 					let a = self.stack.pop();
 					let b = self.stack.pop();
-					self.stack.push(b % a);
+					let result = match instruction {
+						'+' => b.wrapping_add(a),
+						'-' => b.wrapping_sub(a),
+						'*' => b.wrapping_mul(a),
+						// Division by zero gives zero.
+						'/' if a == 0 => 0,
+						'/' => b.wrapping_div(a),
+						_ if a == 0 => 0,
+						_ => b.wrapping_rem(a),
+					};
+					self.stack.push(result);
+
+					if a == 0 && (instruction == '/' || instruction == '%') {
+						if let Some(ref mut hook) = self.division_hook {
+							hook(DivisionByZero { op: instruction, dividend: b, pos: self.pos });
+						}
+					}
+					if let Some(ref mut hook) = self.arithmetic_hook {
+						hook(ArithmeticEvent { op: instruction, left: b, right: a, result });
+					}
 					Action::None
 				},
 				'!' => {
@@ -325,8 +3790,11 @@ impl Interpreter {
 				'<' => Action::ChangeDir(Direction::Left),
 				'^' => Action::ChangeDir(Direction::Up),
 				'v' => Action::ChangeDir(Direction::Down),
+				'r' if self.dialect == Dialect::Extended => self.reflect(),
+				'[' if self.dialect == Dialect::Extended => Action::ChangeDir(self.direction.turn_left()),
+				']' if self.dialect == Dialect::Extended => Action::ChangeDir(self.direction.turn_right()),
 				'?' => {
-					let dir_int = thread_rng().gen_range(0,4);
+					let dir_int = self.rng.gen_range(0,4);
 					match dir_int {
 						0 => Action::ChangeDir(Direction::Right),
 						1 => Action::ChangeDir(Direction::Left),
@@ -365,48 +3833,260 @@ impl Interpreter {
 					Action::None
 				},
 				'.' => {
-					print!("{} ", self.stack.pop());
-					Action::None
+					let value = self.stack.pop();
+					self.emit(OutputEvent::Integer(value))
 				},
 				',' => {
-					print!("{} ", char::from_u32(self.stack.pop()).unwrap());
-					Action::None
+					let value = self.stack.pop();
+					let value = self.char_table.get(&value).map_or(value, |c| i64::from(u32::from(*c)));
+					let event = match self.format.char_mode {
+						CharMode::Unicode => {
+							OutputEvent::Char(to_char(value).unwrap_or(self.format.replacement))
+						},
+						CharMode::Byte => OutputEvent::Byte(value as u8),
+					};
+					self.emit(event)
 				},
 				'#' => Action::Trampoline,
 				'p' => {
-					let x = self.stack.pop();
-					let y = self.stack.pop();
+					let coords = self.pop_coords();
 					let v = self.stack.pop();
 
-					self.program.set_instruction_char([x as usize,y as usize],
-														char::from_u32(v).unwrap());
+					// Writes outside of the program, or to a frozen one, are ignored.
+					if let Some((x, y)) = coords.filter(|_| !self.frozen_grid) {
+						if let Some(limit) = self.max_self_modifications {
+							if self.self_modifications >= limit {
+								self.error = Some(BefungeError::SelfModifyLimitExceeded { limit });
+								return Action::End;
+							}
+						}
+						self.self_modifications += 1;
+
+						let old = self.program.get(x, y);
+						let new = to_char(v).unwrap_or(char::REPLACEMENT_CHARACTER);
+
+						self.program.set(x, y, new);
+						self.last_modification = Some(([y, x], old, new));
+						self.record_write([y, x]);
+
+						self.modified_cells.insert([y, x]);
+						self.metrics.cells_modified = self.modified_cells.len();
+					}
 					Action::None
 				},
 				'g' => {
-					let x = self.stack.pop();
-					let y = self.stack.pop();
-					self.stack.push(self.program.get_instruction_char([x as usize, y as usize]) as u32);
+					// Reads outside of the program give zero.
+					let value = match self.pop_coords() {
+						Some((x, y)) => i64::from(u32::from(self.program.get(x, y))),
+						None => 0,
+					};
+					self.stack.push(value);
 					Action::None
 				},
 				'&' => {
-					let val = self.program.next_value();
+					let val = match self.next_value().or_else(|| self.read_integer()) {
+						Some(val) => {
+							self.metrics.integers_read += 1;
+							val
+						},
+						None => {
+							// Input without a number is left for `~`.
+							if self.peek_input().is_none() {
+								self.input_exhausted = true;
+							}
+							-1
+						},
+					};
 					self.stack.push(val);
 					Action::None
 				},
 				'~' => {
-					let val = self.program.next_value();
+					let val = match self.next_value().or_else(|| self.read_char()) {
+						Some(val) => {
+							self.metrics.chars_read += 1;
+							val
+						},
+						None => {
+							self.input_exhausted = true;
+							-1
+						},
+					};
+					if self.echo_input {
+						if let Some(c) = to_char(val) {
+							if !self.write_output(c.to_string().as_bytes()) {
+								return Action::End;
+							}
+						}
+					}
 					self.stack.push(val);
 					Action::None
 				},
 				'@' => Action::End,
+				' ' => Action::None,
+				_ if self.dialect == Dialect::Extended => self.reflect(),
 				_ => Action::None,
 				}
 			}
 		}
 	}
 
-	/// End the Bufenge program.
-	fn end_program(&self) {
-		println!("\n----- Program Finished -----");
+	/// The next byte of input, without consuming it.
+	///
+	/// Read errors are treated as the end of the input.
+	fn peek_input(&mut self) -> Option<u8> {
+		match self.input.fill_buf() {
+			Ok(bytes) => bytes.first().cloned(),
+			Err(_) => None,
+		}
+	}
+
+	/// Read an integer for `&`, or `None` if no number is left.
+	///
+	/// The buffered input is searched for the first digit, or `-` followed
+	/// by a digit, and everything before it is skipped. The character after
+	/// the number is left for the next read. If there is no number nothing
+	/// is consumed, so a `&` loop cannot get stuck and `~` can still read
+	/// the input.
+	///
+	/// Read errors are treated as the end of the input.
+	fn read_integer(&mut self) -> Option<i64> {
+		let (skip, negative) = {
+			let bytes = self.input.fill_buf().ok()?;
+			let start = (0..bytes.len()).position(|i| {
+				bytes[i].is_ascii_digit()
+					|| (bytes[i] == b'-' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+			})?;
+			(start, bytes[start] == b'-')
+		};
+		self.input.consume(if negative { skip + 1 } else { skip });
+
+		let mut value: i64 = 0;
+		while let Some(b) = self.peek_input().filter(|b| b.is_ascii_digit()) {
+			value = value.wrapping_mul(10).wrapping_add(i64::from(b - b'0'));
+			self.input.consume(1);
+		}
+
+		Some(if negative { value.wrapping_neg() } else { value })
+	}
+
+	/// Read a UTF-8 character for `~`, or `None` at the end of the input.
+	///
+	/// Invalid UTF-8 is read as U+FFFD.
+	fn read_char(&mut self) -> Option<i64> {
+		let first = self.peek_input()?;
+		self.input.consume(1);
+
+		let width = match first {
+			0x00..=0x7f => 1,
+			0xc0..=0xdf => 2,
+			0xe0..=0xef => 3,
+			0xf0..=0xf7 => 4,
+			_ => return Some(i64::from(u32::from(char::REPLACEMENT_CHARACTER))),
+		};
+		let mut bytes = vec![first];
+		while bytes.len() < width {
+			match self.peek_input() {
+				Some(b) if b & 0xc0 == 0x80 => {
+					bytes.push(b);
+					self.input.consume(1);
+				},
+				_ => break,
+			}
+		}
+
+		let c = ::std::str::from_utf8(&bytes).ok()
+			.and_then(|s| s.chars().next())
+			.unwrap_or(char::REPLACEMENT_CHARACTER);
+		Some(i64::from(u32::from(c)))
+	}
+
+	/// Pop the `y` and then `x` coordinates used by `g` and `p`.
+	///
+	/// Returns `None` if they are not a cell of the program, or in strict
+	/// mode are outside of the playfield.
+	fn pop_coords(&mut self) -> Option<(usize, usize)> {
+		let y = to_coord(self.stack.pop());
+		let x = to_coord(self.stack.pop());
+
+		match (x, y) {
+			(Some(x), Some(y)) if self.strict && (x >= PLAYFIELD_WIDTH || y >= PLAYFIELD_HEIGHT) => None,
+			(Some(x), Some(y)) if self.program.contains(x, y) => Some((x, y)),
+			_ => None,
+		}
+	}
+
+	/// Reverse the direction of the pointer.
+	fn reflect(&self) -> Action {
+		Action::ChangeDir(self.direction.reverse())
+	}
+
+	/// Send the output to the channel if there is one, or write it otherwise.
+	///
+	/// Returns the action to take after the output instruction.
+	fn emit(&mut self, event: OutputEvent) -> Action {
+		if event == OutputEvent::Char('\n') || event == OutputEvent::Byte(b'\n') {
+			if let Some(limit) = self.max_newlines {
+				if self.metrics.newlines >= limit {
+					self.error = Some(BefungeError::NewlineLimitExceeded { limit });
+					return Action::End;
+				}
+			}
+			self.metrics.newlines += 1;
+		}
+
+		if let Some(ref sender) = self.output_channel {
+			// Nobody is listening if the receiver has hung up.
+			let _ = sender.send(event);
+		} else {
+			let bytes = match event {
+				OutputEvent::Integer(value) => format!("{} ", self.format.integer(value)).into_bytes(),
+				OutputEvent::Char(c) => match self.newline {
+					Some(ref newline) if c == '\n' => newline.clone().into_bytes(),
+					_ => c.to_string().into_bytes(),
+				},
+				OutputEvent::Byte(b) => vec![b],
+			};
+			if !self.write_output(&bytes) {
+				return Action::End;
+			}
+		}
+
+		self.last_output = Some(event);
+
+		if self.halt_on_first_output {
+			Action::End
+		} else {
+			Action::None
+		}
+	}
+
+	/// Write to the output.
+	///
+	/// All output goes through here so it is written in the order the
+	/// instructions were executed. The output is flushed after each write.
+	///
+	/// If writing fails the error is recorded and `false` is returned, so
+	/// the program can be ended.
+	fn write_output(&mut self, bytes: &[u8]) -> bool {
+		match self.output.write_all(bytes).and_then(|()| self.output.flush()) {
+			Ok(()) => true,
+			Err(e) => {
+				self.error = Some(BefungeError::OutputFailed { kind: e.kind() });
+				false
+			},
+		}
+	}
+
+	/// Track consecutive `p` writes to a cell, warning if there are too many.
+	fn record_write(&mut self, pos: [usize; 2]) {
+		let writes = match self.last_write {
+			Some((last, writes)) if last == pos => writes + 1,
+			_ => 1,
+		};
+		self.last_write = Some((pos, writes));
+
+		if self.p_loop_threshold == Some(writes) {
+			self.warnings.push(Warning::SelfModifyLoop { pos, writes });
+		}
 	}
 }
\ No newline at end of file
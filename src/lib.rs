@@ -0,0 +1,8 @@
+//! RuBefunge-93
+//!
+//! A simple, largely incomplete Befunge interpreter written in Rust.
+
+extern crate rand;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+pub mod befunge;
@@ -2,23 +2,90 @@
 //!
 //! A simple, largely incomplete Befunge interpreter written in Rust.
 
-extern crate rand;
-pub mod befunge;
+extern crate rubefunge_93;
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process;
+
+use rubefunge_93::befunge;
 
 fn main() {
-	// Create a random number generator
-    let values = vec![0u32];
-    //let instructions = vec!["1248::+1> #+?\\# _.@".chars().collect()];
+	let mut stack = Vec::new();
+	let mut input = None;
+	let mut eval = None;
+	let mut path = None;
+
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_ref() {
+			"--stack" => match args.next().map(|list| parse_values(&list)) {
+				Some(Ok(values)) => stack = values,
+				Some(Err(e)) => exit_with(&e),
+				None => exit_with("--stack requires a comma-separated list of values"),
+			},
+			"--input" => match args.next().map(|list| parse_values(&list)) {
+				Some(Ok(values)) => input = Some(values),
+				Some(Err(e)) => exit_with(&e),
+				None => exit_with("--input requires a comma-separated list of values"),
+			},
+			"-e" | "--eval" => match args.next() {
+				Some(source) => eval = Some(source),
+				None => exit_with("--eval requires a program"),
+			},
+			_ if !arg.starts_with('-') && path.is_none() => path = Some(arg),
+			_ => exit_with(&format!("Unknown argument: {}", arg)),
+		}
+	}
+
+	// Values given with --input are read before standard input
+	let values = input.unwrap_or_default();
+
+	let program = match (eval, path) {
+		// A program given with --eval is a single row
+		(Some(source), None) => befunge::Program::new(vec![source.chars().collect()]),
+		(None, Some(path)) => befunge::Program::from_file(&path)
+			.unwrap_or_else(|e| exit_with(&format!("Could not read {}: {}", path, e))),
+		// Otherwise a program piped in on standard input is used, and `&`
+		// and `~` only see the values given with --input.
+		(None, None) if !io::stdin().is_terminal() => befunge::Program::from_reader(io::stdin().lock())
+			.unwrap_or_else(|e| exit_with(&format!("Could not read the program from standard input: {}", e))),
+		// Create the Sieve of Eratosthenes
+		(None, None) => befunge::Program::new(vec![
+			"2>:3g\" \"-!v\\  g30          <".chars().collect(),
+			" |!`\"O\":+1_:.:03p>03g+:\"O\"`|".chars().collect(),
+			" @               ^  p3\\\" \":<".chars().collect(),
+			"2 234567890123456789012345678901234567890123456789012345678901234567890123456789".chars().collect(),
+		]),
+		(Some(_), Some(_)) => exit_with("Give either a program file or --eval, not both"),
+	};
 
-    // Create the Sieve of Eratosthenes
-    let mut instructions = Vec::new();
-    instructions.push("2>:3g\" \"-!v\\  g30          <".chars().collect());
-    instructions.push(" |!`\"O\":+1_:.:03p>03g+:\"O\"`|".chars().collect());
-    instructions.push(" @               ^  p3\\\" \":<".chars().collect());
-    instructions.push("2 234567890123456789012345678901234567890123456789012345678901234567890123456789".chars().collect());
+	let mut interpreter = befunge::Interpreter::with_values(program, values);
+	interpreter.preload_stack(&stack);
 
-	let program = befunge::Program::new(values, instructions);
-	let mut interpreter = befunge::Interpreter::from_program(program);
+	let result = interpreter.execute();
+	eprintln!("\n----- Program Finished -----");
+
+	if let Err(e) = result {
+		exit_with(&format!("Error: {}", e));
+	}
+}
+
+/// Parse a comma-separated list of values, such as `1,2,3`.
+///
+/// Each value must fit in a single (signed 64-bit) stack cell.
+fn parse_values(list: &str) -> Result<Vec<i64>, String> {
+	list.split(',')
+		.map(|value| {
+			let value = value.trim();
+			value.parse::<i64>().map_err(|_| {
+				format!("Invalid value {:?}: values must fit in a signed 64-bit cell", value)
+			})
+		})
+		.collect()
+}
 
-	interpreter.execute();
+fn exit_with(message: &str) -> ! {
+	eprintln!("{}", message);
+	process::exit(1);
 }
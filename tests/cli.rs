@@ -0,0 +1,90 @@
+//! Tests which run the `rubefunge-93` binary.
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with `args` and nothing on standard input.
+fn run(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_rubefunge-93"))
+		.args(args)
+		.stdin(Stdio::null())
+		.output()
+		.unwrap()
+}
+
+/// Run the binary with `args`, writing `input` to its standard input.
+fn run_with_stdin(args: &[&str], input: &str) -> Output {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_rubefunge-93"))
+		.args(args)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+
+	// The binary may exit without reading standard input, for instance when
+	// it is given a program with `-e`, so a broken pipe is expected.
+	match child.stdin.take().unwrap().write_all(input.as_bytes()) {
+		Err(ref e) if e.kind() == ErrorKind::BrokenPipe => {},
+		result => result.unwrap(),
+	}
+	child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn stack_is_preloaded() {
+	let output = run(&["--stack", "1,2", "-e", "+.@"]);
+
+	assert!(output.status.success());
+	assert!(stdout(&output).starts_with("3 "));
+}
+
+#[test]
+fn invalid_stack_value_fails() {
+	let output = run(&["--stack", "1,x", "-e", "+.@"]);
+
+	assert!(!output.status.success());
+}
+
+#[test]
+fn eval_runs_program() {
+	let output = run(&["-e", "99*.@"]);
+
+	assert!(output.status.success());
+	// The banner goes to standard error, so only the program's output is here.
+	assert_eq!(stdout(&output), "81 ");
+}
+
+#[test]
+fn program_is_read_from_stdin() {
+	let output = run_with_stdin(&[], "64+\"!dlroW ,olleH\">:#,_@");
+
+	assert!(output.status.success());
+	assert!(stdout(&output).starts_with("Hello, World!"));
+}
+
+#[test]
+fn eval_takes_precedence_over_stdin() {
+	let output = run_with_stdin(&["-e", "99*.@"], "64+\"!dlroW ,olleH\">:#,_@");
+
+	assert!(output.status.success());
+	assert!(stdout(&output).starts_with("81 "));
+}
+
+#[test]
+fn path_takes_precedence_over_stdin() {
+	let path = env::temp_dir().join(format!("rubefunge-93-cli-{}.bf", std::process::id()));
+	fs::write(&path, "99*.@").unwrap();
+
+	let output = run_with_stdin(&[path.to_str().unwrap()], "64+\"!dlroW ,olleH\">:#,_@");
+	fs::remove_file(&path).unwrap();
+
+	assert!(output.status.success());
+	assert!(stdout(&output).starts_with("81 "));
+}